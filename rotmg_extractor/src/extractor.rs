@@ -104,7 +104,7 @@ impl ParsedClient {
         // construct map of unmapped packet names/types
         let mut names = PacketType::get_name_mappings()
             .iter()
-            .map(|(&pkt_type, name)| (name.to_lowercase(), pkt_type))
+            .map(|&(pkt_type, name)| (name.to_lowercase(), pkt_type))
             .collect::<HashMap<_, _>>();
 
         // construct mappings table
@@ -121,13 +121,14 @@ impl ParsedClient {
         Ok(packets)
     }
 
-    /// Extract a set of mappings from the game client, including RC4 key and
-    /// packet IDs
+    /// Extract a set of mappings from the game client, including RC4 key,
+    /// packet IDs and build version
     pub fn extract_mappings(&self) -> Fallible<Mappings> {
         let rc4 = self.extract_rc4()?;
         let packets = self.extract_packets()?;
+        let version = self.extract_parameters()?.version;
 
-        Ok(Mappings::new(packets, rc4)?)
+        Ok(Mappings::new(packets, rc4)?.with_build_version(version))
     }
 
     /// Extract game client parameters
@@ -13,15 +13,18 @@ const TS_TYPE_DEFINITIONS: &'static str = r#"
  */
 export interface Mappings {
     /**
-     * A map of packet IDs to packet types.
+     * The build version of the client the mappings were extracted from.
      */
-    mappings: Record<number, string>;
+    build_version: string | null;
     /**
-     * A byte array containing both RC4 cipher keys. The outgoing key takes
-     * up the first 13 bytes of the array and the incoming key takes up
-     * the last 13 bytes of the array.
+     * Both RC4 cipher keys, in hex. The outgoing key takes up the first
+     * 13 bytes and the incoming key takes up the last 13 bytes.
      */
-    binary_rc4: number[];
+    rc4: string;
+    /**
+     * A map of packet types to packet IDs.
+     */
+    packets: Record<string, number>;
 }
 
 /**
@@ -0,0 +1,364 @@
+//! Capturing packets sent over connections to files, and replaying them
+//!
+//! # File format
+//!
+//! A capture file starts with a header, followed by any number of records.
+//! All integers are big endian, as in the game protocol.
+//!
+//! The header consists of:
+//!
+//! - the magic bytes `RRCAP`
+//! - the version of the format, as a `u8` (currently 1)
+//! - the length of the mappings, as a `u32`
+//! - the `Mappings` used by the captured connections, in their JSON file
+//!   format, including the build version they were extracted from
+//!
+//! Each record consists of:
+//!
+//! - the time the packet was sent or received, in microseconds since the Unix
+//!   epoch, as a `u64`
+//! - the side of the connection which sent the packet, as a `u8` (0 for the
+//!   client, 1 for the server)
+//! - the ID of the connection, as a `u32`
+//! - the decrypted packet, including its header: the total length of the
+//!   packet as a `u32`, the packet ID as a `u8`, and the contents
+//!
+//! The `recorder` submodule exposes the code used to record packets from
+//! connections, and the `replay` submodule exposes the code used to read them
+//! back.
+
+pub mod recorder;
+pub mod replay;
+
+pub use self::recorder::{Recorder, Recording};
+pub use self::replay::{Pacing, Replay};
+
+use crate::connection::raw_packet::RawPacket;
+use bytes::Bytes;
+use failure_derive::Fail;
+use rotmg_packets::mappings::{Mappings, MappingsError};
+use rotmg_packets::packets::{DirectedPacket, Side};
+use std::io::{Error as IoError, ErrorKind, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The magic bytes at the start of capture files
+pub const MAGIC: &[u8] = b"RRCAP";
+
+/// The version of the capture file format written by this crate
+pub const VERSION: u8 = 1;
+
+/// An error reading or writing a capture file
+#[derive(Debug, Fail)]
+pub enum CaptureError {
+    /// A low level IO error
+    #[fail(display = "IO error: {}", _0)]
+    IoError(IoError),
+
+    /// The file doesn't start with the magic bytes
+    #[fail(display = "Not a capture file")]
+    InvalidMagic,
+
+    /// The file was written with an unsupported version of the format
+    #[fail(display = "Unsupported capture version: {}", _0)]
+    UnsupportedVersion(u8),
+
+    /// The mappings in the header were invalid
+    #[fail(display = "Invalid mappings: {}", _0)]
+    InvalidMappings(MappingsError),
+
+    /// A record contained an invalid sender
+    #[fail(display = "Invalid sender: {}", _0)]
+    InvalidSender(u8),
+
+    /// A record contained an invalid packet size
+    #[fail(display = "Invalid packet size: {}", _0)]
+    InvalidSize(usize),
+}
+
+impl From<IoError> for CaptureError {
+    fn from(e: IoError) -> Self {
+        CaptureError::IoError(e)
+    }
+}
+
+/// A packet sent over a captured connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The time the packet was sent or received, since the Unix epoch
+    pub time: Duration,
+
+    /// The ID of the connection the packet was sent over, unique within a
+    /// capture
+    pub connection: u32,
+
+    /// The side of the connection which sent the packet
+    pub sender: Side,
+
+    /// The decrypted packet, including its header
+    packet: Bytes,
+}
+
+impl Record {
+    /// Create a record of the given packet, sent over the given connection at
+    /// the given time
+    pub fn new<P: DirectedPacket>(time: Duration, connection: u32, packet: &RawPacket<P>) -> Self {
+        Self {
+            time,
+            connection,
+            sender: P::SENDER,
+            packet: packet.clone().into_bytes(),
+        }
+    }
+
+    /// Create a record of the given packet, sent over the given connection now
+    pub fn now<P: DirectedPacket>(connection: u32, packet: &RawPacket<P>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self::new(time, connection, packet)
+    }
+
+    /// Get the ROTMG ID of the packet
+    pub fn packet_id(&self) -> u8 {
+        self.packet[4]
+    }
+
+    /// Get the decrypted binary contents of the packet
+    pub fn raw_contents(&self) -> &[u8] {
+        &self.packet[5..]
+    }
+
+    /// Get the packet as a `RawPacket`, or `None` if it wasn't sent by the
+    /// side of the connection which sends packets of type `P`
+    pub fn to_raw<P: DirectedPacket>(&self) -> Option<RawPacket<P>> {
+        if self.sender == P::SENDER {
+            Some(RawPacket::new(self.packet.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes capture files
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start writing a capture file to the given writer, writing the header
+    /// with the given mappings
+    pub fn new(mut writer: W, mappings: &Mappings) -> Result<Self, CaptureError> {
+        let mut json = vec![];
+        mappings
+            .to_writer(&mut json)
+            .map_err(CaptureError::InvalidMappings)?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&(json.len() as u32).to_be_bytes())?;
+        writer.write_all(&json)?;
+
+        Ok(Self { writer })
+    }
+
+    /// Write a record to the capture file
+    pub fn write(&mut self, record: &Record) -> Result<(), CaptureError> {
+        let sender = match record.sender {
+            Side::Client => 0u8,
+            Side::Server => 1u8,
+        };
+
+        self.writer
+            .write_all(&(record.time.as_micros() as u64).to_be_bytes())?;
+        self.writer.write_all(&[sender])?;
+        self.writer.write_all(&record.connection.to_be_bytes())?;
+        self.writer.write_all(&record.packet)?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> Result<(), CaptureError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Unwrap the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads capture files, iterating over the records in the order they were
+/// written
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    mappings: Mappings,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Start reading a capture file from the given reader, reading the header
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut magic = [0u8; 5];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(CaptureError::InvalidMagic);
+        }
+
+        let version = read_array::<[u8; 1]>(&mut reader)?[0];
+        if version != VERSION {
+            return Err(CaptureError::UnsupportedVersion(version));
+        }
+
+        let len = u32::from_be_bytes(read_array(&mut reader)?);
+        let mappings = Mappings::from_reader((&mut reader).take(u64::from(len)))
+            .map_err(CaptureError::InvalidMappings)?;
+
+        Ok(Self { reader, mappings })
+    }
+
+    /// Get the mappings used by the captured connections
+    pub fn mappings(&self) -> &Mappings {
+        &self.mappings
+    }
+
+    /// Read the next record, or `None` at the end of the file
+    pub fn read(&mut self) -> Result<Option<Record>, CaptureError> {
+        // the file may only end between records
+        let mut time = [0u8; 8];
+        match self.reader.read(&mut time[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut time[1..])?,
+        }
+
+        let time = Duration::from_micros(u64::from_be_bytes(time));
+        let sender = match read_array::<[u8; 1]>(&mut self.reader)?[0] {
+            0 => Side::Client,
+            1 => Side::Server,
+            other => return Err(CaptureError::InvalidSender(other)),
+        };
+        let connection = u32::from_be_bytes(read_array(&mut self.reader)?);
+
+        let size = u32::from_be_bytes(read_array(&mut self.reader)?) as usize;
+        if size < 5 {
+            return Err(CaptureError::InvalidSize(size));
+        }
+
+        // the size isn't trusted to allocate the packet up front, so a corrupt
+        // size can't exhaust memory before the end of the file is reached
+        let mut packet = (size as u32).to_be_bytes().to_vec();
+        (&mut self.reader)
+            .take(size as u64 - 4)
+            .read_to_end(&mut packet)?;
+        if packet.len() != size {
+            return Err(IoError::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(Some(Record {
+            time,
+            connection,
+            sender,
+            packet: packet.into(),
+        }))
+    }
+
+    /// Unwrap the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Record, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// Read a fixed number of bytes from the given reader
+fn read_array<A>(reader: &mut impl Read) -> Result<A, IoError>
+where
+    A: Default + AsMut<[u8]>,
+{
+    let mut array = A::default();
+    reader.read_exact(array.as_mut())?;
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::test_mappings;
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::server::Ping;
+    use rotmg_packets::packets::{ClientPacket, PacketType, ServerPacket};
+
+    pub(crate) fn mappings() -> Mappings {
+        test_mappings(&[PacketType::Ping, PacketType::Pong]).with_build_version("X31.2.3")
+    }
+
+    #[test]
+    fn test_capture_round_trip() {
+        let mappings = mappings();
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+        let records = vec![
+            Record::new(
+                Duration::from_micros(10),
+                0,
+                &RawPacket::from_packet(&pong, &mappings).unwrap(),
+            ),
+            Record::new(
+                Duration::from_micros(20),
+                1,
+                &RawPacket::from_packet(&ping, &mappings).unwrap(),
+            ),
+        ];
+
+        let mut writer = CaptureWriter::new(vec![], &mappings).unwrap();
+        for record in records.iter() {
+            writer.write(record).unwrap();
+        }
+        let buf = writer.into_inner();
+
+        let mut reader = CaptureReader::new(&buf[..]).unwrap();
+        assert_eq!(reader.mappings().build_version(), Some("X31.2.3"));
+        assert_eq!(reader.mappings().get_map(), mappings.get_map());
+
+        let read = (&mut reader).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, records);
+
+        // records can be converted back to packets sent by the same side
+        assert_eq!(read[0].sender, Side::Client);
+        assert_eq!(read[0].packet_id(), 2);
+        assert!(read[0].to_raw::<ServerPacket>().is_none());
+        let raw = read[0].to_raw::<ClientPacket>().unwrap();
+        assert_eq!(raw.to_packet(&mappings).unwrap(), pong);
+
+        // truncated records and headers are errors
+        assert!(CaptureReader::new(&buf[..buf.len() - 1])
+            .unwrap()
+            .any(|r| r.is_err()));
+        assert!(CaptureReader::new(&buf[..3]).is_err());
+        assert!(CaptureReader::new(&b"RRCAP\x02"[..]).is_err());
+    }
+
+    #[test]
+    fn test_capture_oversized_record() {
+        let mut buf = CaptureWriter::new(vec![], &mappings())
+            .unwrap()
+            .into_inner();
+        buf.extend_from_slice(&[0; 8]);
+        buf.push(0);
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&u32::MAX.to_be_bytes());
+        buf.extend_from_slice(&[2, 0, 0]);
+
+        // the record claims to be 4 GiB, but the file ends after a few bytes
+        match CaptureReader::new(&buf[..]).unwrap().read() {
+            Err(CaptureError::IoError(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
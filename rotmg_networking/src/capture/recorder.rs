@@ -0,0 +1,280 @@
+//! Recording packets sent over connections to a capture file
+//!
+//! A `Recorder` is a cloneable handle to a capture file, which can wrap any
+//! number of connections in `Recording` instances. Each recorded connection is
+//! given its own ID, and every packet sent or received through it is written
+//! to the capture file before being passed on. Failing to write a record
+//! doesn't affect the connection, and is only logged.
+
+use super::{CaptureError, CaptureWriter, Record};
+use crate::connection::codec::CodecError;
+use crate::connection::raw_packet::RawPacket;
+use crate::connection::role::Role;
+use crate::connection::Connection;
+use futures::{Sink, Stream};
+use log::warn;
+use rotmg_packets::packets::DirectedPacket;
+use std::io::{Error as IoError, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// The state shared between handles to a recorder
+#[derive(Debug)]
+struct Shared<W: Write> {
+    writer: Mutex<CaptureWriter<W>>,
+    next_connection: AtomicU32,
+}
+
+/// A handle to a capture file which connections can be recorded to.
+///
+/// Records are written synchronously as packets are sent and received, so
+/// the writer should be buffered, e.g. with a `BufWriter`. The capture file
+/// is only flushed when `flush` is called or a recording is closed, not
+/// whenever a recorded connection is flushed.
+#[derive(Debug)]
+pub struct Recorder<W: Write> {
+    shared: Arc<Shared<W>>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Create a recorder writing to the given capture file
+    pub fn new(writer: CaptureWriter<W>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                writer: Mutex::new(writer),
+                next_connection: AtomicU32::new(0),
+            }),
+        }
+    }
+
+    /// Start recording the given connection, assigning it the next connection
+    /// ID
+    pub fn record<R: Role>(&self, connection: Connection<R>) -> Recording<R, W> {
+        let id = self.shared.next_connection.fetch_add(1, Ordering::Relaxed);
+        Recording {
+            inner: connection,
+            recorder: self.clone(),
+            id,
+        }
+    }
+
+    /// Write a record to the capture file
+    pub fn write(&self, record: &Record) -> Result<(), CaptureError> {
+        self.shared.writer.lock().unwrap().write(record)
+    }
+
+    /// Flush the capture file
+    pub fn flush(&self) -> Result<(), CaptureError> {
+        self.shared.writer.lock().unwrap().flush()
+    }
+
+    /// Unwrap the underlying capture writer, or `None` if there are other
+    /// handles to this recorder (including those held by recordings)
+    pub fn into_inner(self) -> Option<CaptureWriter<W>> {
+        Arc::try_unwrap(self.shared)
+            .ok()
+            .map(|shared| shared.writer.into_inner().unwrap())
+    }
+}
+
+impl<W: Write> Clone for Recorder<W> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// Convert an error writing to a capture file to an error for a connection
+fn to_codec_error(e: CaptureError) -> CodecError {
+    match e {
+        CaptureError::IoError(e) => CodecError::IoError(e),
+        other => CodecError::IoError(IoError::other(other.to_string())),
+    }
+}
+
+/// A connection being recorded to a capture file.
+///
+/// Packets are received through the `Stream` implementation and sent through
+/// the `Sink` implementation, just like the wrapped connection. Received
+/// packets are recorded when they're received, and sent packets when they're
+/// queued to be sent. Packets are still passed on if they can't be recorded.
+pub struct Recording<R: Role, W: Write> {
+    inner: Connection<R>,
+    recorder: Recorder<W>,
+    id: u32,
+}
+
+impl<R: Role, W: Write> Recording<R, W> {
+    /// Get the ID of the connection in the capture file
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get a reference to the wrapped connection
+    pub fn get_ref(&self) -> &Connection<R> {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped connection
+    pub fn get_mut(&mut self) -> &mut Connection<R> {
+        &mut self.inner
+    }
+
+    /// Stop recording, unwrapping the connection
+    pub fn into_inner(self) -> Connection<R> {
+        self.inner
+    }
+
+    /// Record a packet sent over this connection, logging any error writing
+    /// the record
+    fn record<P: DirectedPacket>(&self, packet: &RawPacket<P>) {
+        if let Err(e) = self.recorder.write(&Record::now(self.id, packet)) {
+            warn!("Error recording packet on connection {}: {}", self.id, e);
+        }
+    }
+}
+
+impl<R: Role, W: Write> Stream for Recording<R, W> {
+    type Item = Result<RawPacket<R::Receives>, CodecError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let polled = Pin::new(&mut self.inner).poll_next(cx);
+
+        if let Poll::Ready(Some(Ok(packet))) = &polled {
+            self.record(packet);
+        }
+
+        polled
+    }
+}
+
+impl<R: Role, W: Write> Sink<RawPacket<R::Sends>> for Recording<R, W> {
+    type Error = CodecError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: RawPacket<R::Sends>) -> Result<(), Self::Error> {
+        self.record(&item);
+        Pin::new(&mut self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.recorder.flush().map_err(to_codec_error)?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::tests::mappings;
+    use crate::capture::CaptureReader;
+    use crate::connection::{client_listener, server_connection};
+    use futures::{SinkExt, StreamExt};
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::server::Ping;
+    use rotmg_packets::packets::{ClientPacket, ServerPacket, Side};
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_recorder() {
+        let address = SocketAddr::from_str("127.0.0.1:2057").unwrap();
+        let mappings = Arc::new(mappings());
+        let recorder = Recorder::new(CaptureWriter::new(vec![], &mappings).unwrap());
+
+        let mut listener = Box::pin(client_listener(address, mappings.clone()).await.unwrap());
+        let client = server_connection(address, mappings.clone()).await.unwrap();
+        let mut client = recorder.record(client);
+
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+
+        // send a packet from the client before the server connection is
+        // accepted, since the listener waits for data to detect policy
+        // file requests
+        client
+            .send(RawPacket::from_packet(&pong, &mappings).unwrap())
+            .await
+            .unwrap();
+        let mut server = recorder.record(listener.next().await.unwrap().unwrap());
+        assert_eq!((client.id(), server.id()), (0, 1));
+
+        server.next().await.unwrap().unwrap();
+        server
+            .send(RawPacket::from_packet(&ping, &mappings).unwrap())
+            .await
+            .unwrap();
+        client.next().await.unwrap().unwrap();
+
+        drop((client, server));
+        let buf = recorder.into_inner().unwrap().into_inner();
+        let records = CaptureReader::new(&buf[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // each packet is recorded by both ends of the connection
+        let recorded = records
+            .iter()
+            .map(|r| (r.connection, r.sender))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recorded,
+            vec![
+                (0, Side::Client),
+                (1, Side::Client),
+                (1, Side::Server),
+                (0, Side::Server),
+            ]
+        );
+        assert!(records.windows(2).all(|w| w[0].time <= w[1].time));
+
+        let raw = records[1].to_raw::<ClientPacket>().unwrap();
+        assert_eq!(raw.to_packet(&mappings).unwrap(), pong);
+        let raw = records[3].to_raw::<ServerPacket>().unwrap();
+        assert_eq!(raw.to_packet(&mappings).unwrap(), ping);
+    }
+
+    #[tokio::test]
+    async fn test_recorder_errors() {
+        let address = SocketAddr::from_str("127.0.0.1:2059").unwrap();
+        let mappings = Arc::new(mappings());
+
+        // a capture file with only enough space for the header
+        let header = CaptureWriter::new(vec![], &mappings).unwrap().into_inner();
+        let mut file = vec![0u8; header.len()];
+        let recorder = Recorder::new(CaptureWriter::new(&mut file[..], &mappings).unwrap());
+
+        let mut listener = Box::pin(client_listener(address, mappings.clone()).await.unwrap());
+        let client = server_connection(address, mappings.clone()).await.unwrap();
+        let mut client = recorder.record(client);
+
+        // packets are still sent and received when they can't be recorded
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        client
+            .send(RawPacket::from_packet(&pong, &mappings).unwrap())
+            .await
+            .unwrap();
+        let mut server = listener.next().await.unwrap().unwrap();
+        server.next().await.unwrap().unwrap();
+
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+        server
+            .send(RawPacket::from_packet(&ping, &mappings).unwrap())
+            .await
+            .unwrap();
+        let raw = client.next().await.unwrap().unwrap();
+        assert_eq!(raw.to_packet(&mappings).unwrap(), ping);
+        assert!(recorder.write(&Record::now(0, &raw)).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Replaying packets from a capture file
+//!
+//! A `Replay` loads all records from a capture file, sorted by the time they
+//! were recorded. The packets can then be iterated over immediately, or
+//! streamed with the same delays between them as when they were recorded, to
+//! drive tests without a live connection.
+
+use super::{CaptureError, CaptureReader, Record};
+use crate::connection::raw_packet::RawPacket;
+use futures::{stream, Stream};
+use rotmg_packets::mappings::Mappings;
+use rotmg_packets::packets::DirectedPacket;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+/// How quickly a replay should yield records
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pacing {
+    /// Yield records as quickly as possible
+    Instant,
+
+    /// Yield records with the same delays between them as when they were
+    /// recorded
+    RealTime,
+}
+
+/// The records loaded from a capture file
+#[derive(Debug, Clone)]
+pub struct Replay {
+    mappings: Arc<Mappings>,
+    records: Vec<Record>,
+}
+
+impl Replay {
+    /// Load all records from the given capture file reader
+    pub fn from_reader(reader: impl Read) -> Result<Self, CaptureError> {
+        let mut reader = CaptureReader::new(reader)?;
+        let mut records = (&mut reader).collect::<Result<Vec<_>, _>>()?;
+
+        // records from different connections may be written slightly out of
+        // order, but records from the same connection never are
+        records.sort_by_key(|r| r.time);
+
+        Ok(Self {
+            mappings: Arc::new(reader.mappings),
+            records,
+        })
+    }
+
+    /// Load all records from the capture file at the given path
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Get the mappings used by the captured connections
+    pub fn mappings(&self) -> &Arc<Mappings> {
+        &self.mappings
+    }
+
+    /// Get the records in the order they were recorded
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Only keep the records from the connection with the given ID
+    pub fn connection(mut self, id: u32) -> Self {
+        self.records.retain(|r| r.connection == id);
+        self
+    }
+
+    /// Iterate over the packets of type `P` in the order they were recorded,
+    /// along with the ID of the connection they were sent over
+    pub fn packets<P: DirectedPacket>(&self) -> impl Iterator<Item = (u32, RawPacket<P>)> + '_ {
+        self.records
+            .iter()
+            .filter_map(|r| r.to_raw().map(|raw| (r.connection, raw)))
+    }
+
+    /// Stream the records in the order they were recorded, with the given
+    /// pacing
+    pub fn into_stream(self, pacing: Pacing) -> impl Stream<Item = Record> {
+        let first = self.records.first().map_or(Duration::default(), |r| r.time);
+        let start = Instant::now();
+
+        stream::unfold(self.records.into_iter(), move |mut records| async move {
+            let record = records.next()?;
+
+            if pacing == Pacing::RealTime {
+                sleep_until(start + (record.time - first)).await;
+            }
+
+            Some((record, records))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::tests::mappings;
+    use crate::capture::CaptureWriter;
+    use futures::StreamExt;
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::server::Ping;
+    use rotmg_packets::packets::{ClientPacket, ServerPacket};
+
+    fn capture() -> Vec<u8> {
+        let mappings = mappings();
+        let pong =
+            RawPacket::from_packet(&ClientPacket::Pong(Pong { serial: 1, time: 2 }), &mappings)
+                .unwrap();
+        let ping =
+            RawPacket::from_packet(&ServerPacket::Ping(Ping { serial: 3 }), &mappings).unwrap();
+
+        // written out of order, as if by two connections racing
+        let mut writer = CaptureWriter::new(vec![], &mappings).unwrap();
+        let ms = Duration::from_millis;
+        writer.write(&Record::new(ms(1000), 0, &pong)).unwrap();
+        writer.write(&Record::new(ms(1050), 1, &ping)).unwrap();
+        writer.write(&Record::new(ms(1020), 0, &ping)).unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_replay() {
+        let replay = Replay::from_reader(&capture()[..]).unwrap();
+        assert_eq!(replay.mappings().build_version(), Some("X31.2.3"));
+
+        // records are sorted by time
+        let times = replay
+            .records()
+            .iter()
+            .map(|r| r.time.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![1000, 1020, 1050]);
+
+        // and packets can be filtered by sender and connection
+        let pongs = replay.packets::<ClientPacket>().collect::<Vec<_>>();
+        assert_eq!(pongs.len(), 1);
+        assert_eq!(
+            pongs[0].1.to_packet(replay.mappings()).unwrap(),
+            ClientPacket::Pong(Pong { serial: 1, time: 2 })
+        );
+        let pings = replay.connection(1).packets::<ServerPacket>().count();
+        assert_eq!(pings, 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_pacing() {
+        let replay = Replay::from_reader(&capture()[..]).unwrap();
+
+        let records = replay.clone().into_stream(Pacing::Instant);
+        assert_eq!(records.collect::<Vec<_>>().await, replay.records());
+
+        // real time replays take as long as the capture
+        let start = Instant::now();
+        let records = replay.clone().into_stream(Pacing::RealTime);
+        assert_eq!(records.collect::<Vec<_>>().await, replay.records());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
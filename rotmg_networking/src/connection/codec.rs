@@ -1,12 +1,16 @@
 //! Tokio codec for framing ROTMG packets as `RawPacket` instances
 
-use super::raw_packet::RawPacket;
+use super::raw_packet::{Error as RawPacketError, RawPacket};
+use super::role::{AsClient, AsServer, Role};
 use crate::rc4::Rc4;
-use bytes::{Buf, BytesMut};
+use bytes::BytesMut;
 use failure_derive::Fail;
 use rotmg_packets::mappings::{Mappings, RC4_LEN};
-use std::io::{Cursor, Error as IoError};
-use tokio::codec::{Decoder, Encoder};
+use rotmg_packets::packets::PacketType;
+use std::convert::TryInto;
+use std::io::Error as IoError;
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
 
 /// Get the two RC4 ciphers
 pub fn get_ciphers(mappings: &Mappings) -> (Rc4, Rc4) {
@@ -17,10 +21,13 @@ pub fn get_ciphers(mappings: &Mappings) -> (Rc4, Rc4) {
 /// The codec for framing and encrypting/decrypting ROTMG packets. This struct
 /// contains the minimum state necessary - just the RC4 ciphers for sending and
 /// receiving packets.
-#[derive(Clone)]
-pub struct Codec {
+///
+/// The type parameter `R` is the role played by this side of the connection,
+/// which determines the type of packets that can be sent and received.
+pub struct Codec<R: Role> {
     recv_rc4: Rc4,
     send_rc4: Rc4,
+    role: PhantomData<fn() -> R>,
 }
 
 /// An error that occurred while reading or writing a packet
@@ -33,6 +40,10 @@ pub enum CodecError {
     /// The packet size was invalid
     #[fail(display = "Invalid packet size: {}", _0)]
     InvalidSize(usize),
+
+    /// A packet could not be converted to a `RawPacket` to be sent
+    #[fail(display = "Packet error: {}", _0)]
+    PacketError(RawPacketError<PacketType>),
 }
 
 impl From<IoError> for CodecError {
@@ -41,24 +52,44 @@ impl From<IoError> for CodecError {
     }
 }
 
-impl Codec {
+impl Codec<AsServer> {
     /// Construct a new codec for communicating with a game client - i.e. with
     /// this side of the connection acting as the server
     pub fn new_as_server(mappings: &Mappings) -> Self {
         let (recv_rc4, send_rc4) = get_ciphers(mappings);
-        Self { recv_rc4, send_rc4 }
+        Self {
+            recv_rc4,
+            send_rc4,
+            role: PhantomData,
+        }
     }
+}
 
-    /// Construct a new codec for communicating with a game client - i.e. with
+impl Codec<AsClient> {
+    /// Construct a new codec for communicating with a game server - i.e. with
     /// this side of the connection acting as the client
     pub fn new_as_client(mappings: &Mappings) -> Self {
         let (send_rc4, recv_rc4) = get_ciphers(mappings);
-        Self { recv_rc4, send_rc4 }
+        Self {
+            recv_rc4,
+            send_rc4,
+            role: PhantomData,
+        }
+    }
+}
+
+impl<R: Role> Clone for Codec<R> {
+    fn clone(&self) -> Self {
+        Self {
+            recv_rc4: self.recv_rc4.clone(),
+            send_rc4: self.send_rc4.clone(),
+            role: PhantomData,
+        }
     }
 }
 
-impl Decoder for Codec {
-    type Item = RawPacket;
+impl<R: Role> Decoder for Codec<R> {
+    type Item = RawPacket<R::Receives>;
     type Error = CodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
@@ -68,10 +99,7 @@ impl Decoder for Codec {
         }
 
         // get the total length of the packet
-        let packet_size = {
-            let mut cursor = Cursor::new(&src);
-            cursor.get_u32_be() as usize
-        };
+        let packet_size = u32::from_be_bytes(src[..4].try_into().unwrap()) as usize;
 
         // the smallest valid packet is just a header, 5 bytes
         if packet_size < 5 {
@@ -95,22 +123,52 @@ impl Decoder for Codec {
     }
 }
 
-impl Encoder for Codec {
-    type Item = RawPacket;
+impl<R: Role> Encoder<RawPacket<R::Sends>> for Codec<R> {
     type Error = CodecError;
 
-    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        // convert the packet back into bytes
-        let packet = item.into_bytes();
-
-        // make the packet mutable so we can encrypt the data
-        let mut packet = BytesMut::from(packet);
-
-        // encrypt the packet contents
-        self.send_rc4.process(&mut packet[5..]);
+    fn encode(&mut self, item: RawPacket<R::Sends>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        // write the packet...
+        let start = dst.len();
+        dst.extend_from_slice(&item.into_bytes());
 
-        // finally, write the packet
-        dst.extend_from_slice(&packet[..]);
+        // ...then encrypt the packet contents in place
+        self.send_rc4.process(&mut dst[start + 5..]);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::test_mappings;
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::server::Ping;
+    use rotmg_packets::packets::{ClientPacket, ServerPacket};
+
+    #[test]
+    fn test_codec_round_trip() {
+        let mappings = test_mappings(&[PacketType::Ping, PacketType::Pong]);
+
+        let mut client = Codec::new_as_client(&mappings);
+        let mut server = Codec::new_as_server(&mappings);
+        let mut buf = BytesMut::new();
+
+        // client to server
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let raw = RawPacket::from_packet(&pong, &mappings).unwrap();
+        client.encode(raw.clone(), &mut buf).unwrap();
+        assert_ne!(&buf[5..], raw.raw_contents());
+
+        let decoded = server.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded.to_packet(&mappings).unwrap(), pong);
+        assert!(buf.is_empty());
+
+        // server to client
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+        let raw = RawPacket::from_packet(&ping, &mappings).unwrap();
+        server.encode(raw, &mut buf).unwrap();
+
+        let decoded = client.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded.to_packet(&mappings).unwrap(), ping);
+    }
+}
@@ -9,27 +9,57 @@
 //! customize the behavior.
 
 pub mod codec;
+pub mod packet_codec;
 pub mod policy;
 pub mod raw_packet;
+pub mod role;
 
 use self::codec::Codec;
+use self::packet_codec::PacketCodec;
 use self::policy::handle_policy_request;
-use futures::{Future, Stream};
+use self::role::{AsClient, AsServer};
+use futures::stream::{self, FuturesUnordered};
+use futures::{Stream, StreamExt};
 use rotmg_packets::mappings::Mappings;
-use std::convert::identity;
-use std::io::{Error as IoError, Result as IoResult};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
 use std::net::SocketAddr;
-use tokio::codec::{Decoder, Framed};
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_util::codec::{Decoder, Framed};
 
-/// A framed TCP connection that operates on `RawPacket` instances
-pub type Connection = Framed<TcpStream, Codec>;
+/// How long a new connection has to send its first data, which is needed to
+/// tell policy file requests apart from game connections
+pub const FIRST_DATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A framed TCP connection that operates on `RawPacket` instances, with this
+/// side of the connection playing the role `R`.
+///
+/// Packets are received through the `Stream` implementation, and sent through
+/// the `Sink` implementation.
+pub type Connection<R> = Framed<TcpStream, Codec<R>>;
+
+/// A framed TCP connection that operates on `Packet` instances, as decoded and
+/// encoded by a `PacketCodec`
+pub type PacketConnection<R> = Framed<TcpStream, PacketCodec<R>>;
 
 /// Configure a stream for either client or server communication
-fn configure_stream(s: TcpStream) -> IoResult<TcpStream> {
-    s.set_nodelay(true)?;
+fn configure_stream(s: &TcpStream) -> IoResult<()> {
+    s.set_nodelay(true)
+}
+
+/// Check whether a newly accepted connection is a ROTMG client, handling it if
+/// it's a policy file request instead
+async fn detect_client(s: TcpStream) -> IoResult<Option<TcpStream>> {
+    configure_stream(&s)?;
 
-    Ok(s)
+    match timeout(FIRST_DATA_TIMEOUT, handle_policy_request(s)).await {
+        Ok(detected) => detected,
+        Err(_) => Err(IoError::new(
+            ErrorKind::TimedOut,
+            "no data received from new connection",
+        )),
+    }
 }
 
 /// Start a listener accepting ROTMG client connections on the given socket
@@ -37,17 +67,46 @@ fn configure_stream(s: TcpStream) -> IoResult<TcpStream> {
 ///
 /// A stream of framed connections is returned, providing bidirectional
 /// communication by way of `RawPacket` instances. Policy file requests will
-/// also be handled automatically by this function.
-pub fn client_listener(
-    address: &SocketAddr,
+/// also be handled automatically by this function. An error accepting one
+/// connection is yielded by the stream, which will continue to accept further
+/// connections afterwards.
+///
+/// Connections are only yielded once they've sent some data, to tell them
+/// apart from policy file requests. New connections are accepted while
+/// waiting for earlier ones, and connections which don't send anything within
+/// `FIRST_DATA_TIMEOUT` are dropped with an error.
+pub async fn client_listener(
+    address: SocketAddr,
     mappings: impl AsRef<Mappings> + Send + 'static,
-) -> IoResult<impl Stream<Item = Connection, Error = IoError> + Send> {
-    let stream = TcpListener::bind(address)?
-        .incoming()
-        .and_then(configure_stream)
-        .and_then(handle_policy_request)
-        .filter_map(identity)
-        .map(move |s| Codec::new_as_server(mappings.as_ref()).framed(s));
+) -> IoResult<impl Stream<Item = IoResult<Connection<AsServer>>> + Send> {
+    let listener = TcpListener::bind(address).await?;
+    let pending = FuturesUnordered::new();
+
+    let stream = stream::unfold(
+        (listener, pending, mappings),
+        |(listener, mut pending, mappings)| async {
+            // keep accepting connections while waiting for the first data
+            // from those already accepted
+            let accepted = loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((s, _)) => pending.push(detect_client(s)),
+                        Err(e) => break Err(e),
+                    },
+                    Some(detected) = pending.next(), if !pending.is_empty() => {
+                        match detected {
+                            Ok(Some(s)) => break Ok(s),
+                            Ok(None) => {}
+                            Err(e) => break Err(e),
+                        }
+                    }
+                }
+            };
+
+            let connection = accepted.map(|s| Codec::new_as_server(mappings.as_ref()).framed(s));
+            Some((connection, (listener, pending, mappings)))
+        },
+    );
 
     Ok(stream)
 }
@@ -57,11 +116,99 @@ pub fn client_listener(
 ///
 /// A framed connection is returned, providing bidirectional communication by
 /// way of `RawPacket` instances.
-pub fn server_connection(
-    address: &SocketAddr,
-    mappings: impl AsRef<Mappings> + Send + 'static,
-) -> impl Future<Item = Connection, Error = IoError> + Send {
-    TcpStream::connect(address)
-        .and_then(configure_stream)
-        .map(move |s| Codec::new_as_client(mappings.as_ref()).framed(s))
+pub async fn server_connection(
+    address: SocketAddr,
+    mappings: impl AsRef<Mappings>,
+) -> IoResult<Connection<AsClient>> {
+    let s = TcpStream::connect(address).await?;
+    configure_stream(&s)?;
+
+    Ok(Codec::new_as_client(mappings.as_ref()).framed(s))
+}
+
+/// Create mappings for tests, giving the given packet types IDs counting up
+/// from 1
+#[cfg(test)]
+pub(crate) fn test_mappings(types: &[rotmg_packets::packets::PacketType]) -> Mappings {
+    let map = types.iter().zip(1..).map(|(&typ, id)| (id, typ)).collect();
+    Mappings::new(map, &"0123456789abcdef".repeat(4)[..52]).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::policy::{POLICY_FILE, POLICY_REQUEST};
+    use super::raw_packet::RawPacket;
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::{ClientPacket, PacketType};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_connections() {
+        let address = SocketAddr::from_str("127.0.0.1:2051").unwrap();
+        let mappings = Arc::new(test_mappings(&[PacketType::Pong]));
+
+        // accept a single game connection in the background
+        let listener = client_listener(address, mappings.clone()).await.unwrap();
+        let accepted = tokio::spawn(async { Box::pin(listener).next().await });
+
+        // policy file requests are answered without yielding a connection
+        let mut policy = TcpStream::connect(address).await.unwrap();
+        policy.write_all(POLICY_REQUEST).await.unwrap();
+        let mut file = vec![];
+        policy.read_to_end(&mut file).await.unwrap();
+        assert_eq!(&file[..], POLICY_FILE);
+
+        // packets sent by the client are received by the server
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let mut client = server_connection(address, mappings.clone()).await.unwrap();
+        let raw = RawPacket::from_packet(&pong, &mappings).unwrap();
+        client.send(raw).await.unwrap();
+
+        let mut server = accepted.await.unwrap().unwrap().unwrap();
+        let raw = server.next().await.unwrap().unwrap();
+        assert_eq!(raw.to_packet(&mappings).unwrap(), pong);
+    }
+
+    #[tokio::test]
+    async fn test_slow_connections() {
+        let address = SocketAddr::from_str("127.0.0.1:2058").unwrap();
+        let mappings = Arc::new(test_mappings(&[PacketType::Pong]));
+        let mut listener = Box::pin(client_listener(address, mappings.clone()).await.unwrap());
+
+        // connections which send nothing, or part of a policy file request,
+        // don't stop other clients from being accepted
+        let _idle = TcpStream::connect(address).await.unwrap();
+        let mut partial = TcpStream::connect(address).await.unwrap();
+        partial.write_all(&POLICY_REQUEST[..5]).await.unwrap();
+
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let mut client = server_connection(address, mappings.clone()).await.unwrap();
+        let raw = RawPacket::from_packet(&pong, &mappings).unwrap();
+        client.send(raw).await.unwrap();
+
+        let accept = async {
+            let mut addresses = vec![];
+            for _ in 0..2 {
+                let server = listener.next().await.unwrap().unwrap();
+                addresses.push(server.get_ref().peer_addr().unwrap());
+            }
+            addresses.sort();
+            addresses
+        };
+        let accepted = tokio::time::timeout(Duration::from_secs(5), accept)
+            .await
+            .expect("clients weren't accepted");
+
+        // the partial request is treated as a game connection
+        let mut expected = vec![
+            partial.local_addr().unwrap(),
+            client.get_ref().local_addr().unwrap(),
+        ];
+        expected.sort();
+        assert_eq!(accepted, expected);
+    }
 }
@@ -0,0 +1,227 @@
+//! Tokio codec for framing ROTMG packets as `Packet` instances
+//!
+//! `PacketCodec` wraps a `Codec`, decoding the `RawPacket` instances it
+//! receives using its own `Mappings`. It can be restricted to decoding only
+//! some types of packets, which is useful when only a few types are of
+//! interest - the rest are passed through as `RawPacket` instances without the
+//! cost of decoding them.
+
+use super::codec::{Codec, CodecError};
+use super::raw_packet::{Error, RawPacket};
+use super::role::{AsClient, AsServer, Role};
+use bytes::BytesMut;
+use rotmg_packets::mappings::Mappings;
+use rotmg_packets::packets::{ClientPacket, DirectedPacket, PacketType, ServerPacket};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A packet received by a `PacketCodec`
+#[derive(Debug)]
+pub enum Decoded<P: DirectedPacket> {
+    /// A packet which was decoded successfully
+    Packet(P),
+
+    /// A packet which was passed through without decoding, since its type
+    /// wasn't selected for decoding
+    Raw(RawPacket<P>),
+}
+
+/// A packet received by a `PacketCodec` which could not be decoded
+#[derive(Debug)]
+pub struct DecodeFailure<P: DirectedPacket> {
+    /// The packet which could not be decoded
+    pub raw: RawPacket<P>,
+
+    /// The error raised while decoding the packet
+    pub error: Error<u8>,
+}
+
+/// The codec for framing ROTMG packets and converting them to and from
+/// `Packet` instances, using the `Mappings` it owns.
+///
+/// Each packet received is yielded as `Ok` with the decoded packet (or the
+/// `RawPacket` if its type wasn't selected for decoding), or as `Err` with the
+/// `RawPacket` if it couldn't be decoded. Packets with no mapping for their ID
+/// are decoded as unknown packets. Both packets and `RawPacket` instances can
+/// be sent.
+pub struct PacketCodec<R: Role> {
+    codec: Codec<R>,
+    mappings: Arc<Mappings>,
+    selected: Option<HashSet<PacketType>>,
+}
+
+impl<R: Role> PacketCodec<R> {
+    /// Wrap the given codec, using the given mappings to decode and encode
+    /// packets. All packets received will be decoded.
+    ///
+    /// An existing `Connection` can be converted to a `PacketConnection` by
+    /// wrapping its codec with `Framed::map_codec`.
+    pub fn new(codec: Codec<R>, mappings: impl Into<Arc<Mappings>>) -> Self {
+        Self {
+            codec,
+            mappings: mappings.into(),
+            selected: None,
+        }
+    }
+
+    /// Only decode packets of the given types, passing all others through as
+    /// `RawPacket` instances, including those with no mapping for their ID
+    pub fn decode_only(mut self, types: impl IntoIterator<Item = PacketType>) -> Self {
+        self.selected = Some(types.into_iter().collect());
+        self
+    }
+
+    /// Get the mappings used by this codec
+    pub fn mappings(&self) -> &Arc<Mappings> {
+        &self.mappings
+    }
+
+    /// Unwrap the underlying codec
+    pub fn into_inner(self) -> Codec<R> {
+        self.codec
+    }
+
+    /// Check whether a packet should be decoded
+    fn is_selected(&self, raw: &RawPacket<R::Receives>) -> bool {
+        match &self.selected {
+            None => true,
+            Some(selected) => raw
+                .packet_type(&self.mappings)
+                .is_some_and(|typ| selected.contains(&typ)),
+        }
+    }
+}
+
+impl PacketCodec<AsServer> {
+    /// Construct a new codec for communicating with a game client - i.e. with
+    /// this side of the connection acting as the server
+    pub fn new_as_server(mappings: impl Into<Arc<Mappings>>) -> Self {
+        let mappings = mappings.into();
+        Self::new(Codec::new_as_server(&mappings), mappings)
+    }
+}
+
+impl PacketCodec<AsClient> {
+    /// Construct a new codec for communicating with a game server - i.e. with
+    /// this side of the connection acting as the client
+    pub fn new_as_client(mappings: impl Into<Arc<Mappings>>) -> Self {
+        let mappings = mappings.into();
+        Self::new(Codec::new_as_client(&mappings), mappings)
+    }
+}
+
+impl<R: Role> Clone for PacketCodec<R> {
+    fn clone(&self) -> Self {
+        Self {
+            codec: self.codec.clone(),
+            mappings: self.mappings.clone(),
+            selected: self.selected.clone(),
+        }
+    }
+}
+
+impl<R: Role> Decoder for PacketCodec<R> {
+    type Item = Result<Decoded<R::Receives>, DecodeFailure<R::Receives>>;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let raw = match self.codec.decode(src)? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+
+        if !self.is_selected(&raw) {
+            return Ok(Some(Ok(Decoded::Raw(raw))));
+        }
+
+        let decoded = match raw.to_packet(&self.mappings) {
+            Ok(packet) => Ok(Decoded::Packet(packet)),
+            Err(error) => Err(DecodeFailure { raw, error }),
+        };
+
+        Ok(Some(decoded))
+    }
+}
+
+impl<R: Role> Encoder<RawPacket<R::Sends>> for PacketCodec<R> {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: RawPacket<R::Sends>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+/// Implement `Encoder` for the packets sent by the given role
+macro_rules! packet_encoder {
+    ($($role:ident => $packet:ident),*) => {
+        $(
+            impl Encoder<$packet> for PacketCodec<$role> {
+                type Error = CodecError;
+
+                fn encode(&mut self, item: $packet, dst: &mut BytesMut) -> Result<(), Self::Error> {
+                    let raw = RawPacket::from_packet(&item, &self.mappings)
+                        .map_err(CodecError::PacketError)?;
+                    self.codec.encode(raw, dst)
+                }
+            }
+        )*
+    };
+}
+
+packet_encoder! {
+    AsServer => ServerPacket,
+    AsClient => ClientPacket
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::test_mappings;
+    use rotmg_packets::packets::client::{Escape, Pong};
+    use rotmg_packets::packets::server::Ping;
+
+    fn mappings() -> Mappings {
+        test_mappings(&[PacketType::Ping, PacketType::Pong, PacketType::Escape])
+    }
+
+    #[test]
+    fn test_packet_codec() {
+        let mut client = PacketCodec::new_as_client(mappings());
+        let mut server = PacketCodec::new_as_server(mappings()).decode_only(vec![PacketType::Pong]);
+        let mut buf = BytesMut::new();
+
+        // selected packets are decoded
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        client.encode(pong.clone(), &mut buf).unwrap();
+        match server.decode(&mut buf).unwrap().unwrap() {
+            Ok(Decoded::Packet(packet)) => assert_eq!(packet, pong),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // other packets are passed through
+        let escape = ClientPacket::Escape(Escape {});
+        client.encode(escape.clone(), &mut buf).unwrap();
+        match server.decode(&mut buf).unwrap().unwrap() {
+            Ok(Decoded::Raw(raw)) => assert_eq!(raw.to_packet(&mappings()).unwrap(), escape),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // undecodable packets are returned in raw form
+        let raw = RawPacket::<ClientPacket>::new(vec![0, 0, 0, 6, 2, 0].into());
+        client.encode(raw, &mut buf).unwrap();
+        match server.decode(&mut buf).unwrap().unwrap() {
+            Err(DecodeFailure { raw, .. }) => assert_eq!(raw.raw_contents(), &[0]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(buf.is_empty());
+
+        // all packets are decoded by default
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+        server.encode(ping.clone(), &mut buf).unwrap();
+        match client.decode(&mut buf).unwrap().unwrap() {
+            Ok(Decoded::Packet(packet)) => assert_eq!(packet, ping),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
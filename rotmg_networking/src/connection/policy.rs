@@ -5,13 +5,10 @@
 //! function to handle these requests, replying with a policy file which allows
 //! connections from any host to any local port from flash clients.
 
-use crate::ext::TcpStreamExt;
-use futures::future::Loop;
-use futures::{future, Future};
+use crate::ext::peek_max;
 use log::{debug, trace};
-use std::io::Error as IoError;
-use std::net::Shutdown;
-use tokio::io::write_all;
+use std::io::Result as IoResult;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 
 /// The binary message denoting a policy file request
@@ -36,34 +33,47 @@ pub const POLICY_FILE: &[u8] = br#"
 /// `None` will be returned when a policy file request is detected and handled.
 /// `Some(TcpStream)` will be returned when a regular connection is detected;
 /// all data received from the stream will remain in the buffer.
-pub fn handle_policy_request(
-    stream: TcpStream,
-) -> impl Future<Item = Option<TcpStream>, Error = IoError> {
-    future::loop_fn(
-        (stream, vec![]),
-        move |(stream, bytes)| -> Box<dyn Future<Item = _, Error = _> + Send> {
-            if &bytes[..] == POLICY_REQUEST {
-                // this is definitely a policy file request
-                // send the policy file, then shutdown the socket and break with
-                // none to indicate that this wasn't a game connection
-                debug!("Sending policy file to {}", stream.peer_addr().unwrap());
+///
+/// This waits until the peer sends some data, so it should be run with a
+/// timeout. If the peer sends part of a policy file request and nothing more
+/// arrives by the time it's peeked again, it's treated as a regular
+/// connection.
+pub async fn handle_policy_request(mut stream: TcpStream) -> IoResult<Option<TcpStream>> {
+    let mut bytes = vec![];
+
+    loop {
+        if &bytes[..] == POLICY_REQUEST {
+            // this is definitely a policy file request
+            // send the policy file, then shutdown the socket and return none
+            // to indicate that this wasn't a game connection
+            debug!("Sending policy file to {}", stream.peer_addr()?);
 
-                Box::new(
-                    write_all(stream, POLICY_FILE)
-                        .and_then(|(stream, _)| stream.shutdown(Shutdown::Both))
-                        .map(|_| Loop::Break(None)),
-                )
-            } else if POLICY_REQUEST.starts_with(&bytes[..]) {
-                trace!("Potential policy file request: {:?}", bytes);
+            stream.write_all(POLICY_FILE).await?;
+            stream.shutdown().await?;
+            return Ok(None);
+        } else if POLICY_REQUEST.starts_with(&bytes[..]) {
+            trace!("Potential policy file request: {:?}", bytes);
 
-                // this may be a policy file request, but we need more bytes
-                Box::new(stream.peek_max(POLICY_REQUEST.len()).map(Loop::Continue))
-            } else {
-                trace!("Not a policy file request: {:?}", bytes);
+            // this may be a policy file request, but we need more bytes
+            let peeked = peek_max(&stream, POLICY_REQUEST.len()).await?;
 
-                // this is not a policy file request
-                Box::new(future::ok(Loop::Break(Some(stream))))
+            if peeked.is_empty() {
+                // the connection was closed before sending anything, which
+                // will be seen when reading packets from it
+                return Ok(Some(stream));
+            } else if peeked.len() == bytes.len() {
+                // peeking again returns the same bytes straight away rather
+                // than waiting for more, so stop instead of spinning
+                trace!("Incomplete policy file request: {:?}", bytes);
+                return Ok(Some(stream));
             }
-        },
-    )
+
+            bytes = peeked;
+        } else {
+            trace!("Not a policy file request: {:?}", bytes);
+
+            // this is not a policy file request
+            return Ok(Some(stream));
+        }
+    }
 }
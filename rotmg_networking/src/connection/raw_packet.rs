@@ -1,12 +1,18 @@
 //! A representation of packets that have been received and decrypted, but have
 //! not yet been deserialized into `Packet` instances
 
-use bytes::{Bytes, IntoBuf};
+use bytes::Bytes;
 use failure_derive::Fail;
 use rotmg_packets::adapter::Error as AdapterError;
+use rotmg_packets::dissect::{dissect, Dissection};
 use rotmg_packets::mappings::Mappings;
-use rotmg_packets::packets::{Packet, PacketType};
-use std::fmt::Debug;
+use rotmg_packets::packets::view::PacketView;
+use rotmg_packets::packets::{
+    DecodeError, DecodeMode, DecodeOptions, DirectedPacket, Packet, PacketType,
+};
+use std::convert::TryFrom;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::marker::PhantomData;
 
 /// A decrypted and properly framed packet represented as bytes.
 ///
@@ -16,9 +22,13 @@ use std::fmt::Debug;
 /// inspecting the type, and if there is an error converting the `RawPacket` to
 /// a `Packet`, the packet is still available in binary form and may still be
 /// used.
-#[derive(Clone)]
-pub struct RawPacket {
+///
+/// The type parameter `P` is the type of packet contained, either
+/// `ClientPacket` or `ServerPacket`, which determines the side of the
+/// connection that sends it.
+pub struct RawPacket<P: DirectedPacket> {
     bytes: Bytes,
+    direction: PhantomData<fn() -> P>,
 }
 
 /// An error converting between a `RawPacket` and `Packet`.
@@ -30,16 +40,36 @@ pub enum Error<T: Debug + Send + Sync + 'static> {
     #[fail(display = "Adapter error: {}", _0)]
     AdapterError(AdapterError),
 
+    /// An error decoding the contents of the packet
+    #[fail(display = "Decode error: {}", _0)]
+    DecodeError(DecodeError),
+
     /// No mapping exists for the given packet type
     #[fail(display = "Unmapped packet type: {:?}", _0)]
     UnmappedPacketType(T),
+
+    /// The encoded packet would be too large for its size to be stored in the
+    /// packet header
+    #[fail(display = "Packet too large: {} bytes", _0)]
+    PacketTooLarge(usize),
+}
+
+/// Get the total size of a packet with the given length of contents, as
+/// stored in the packet header, or `None` if it doesn't fit
+fn packet_size(contents_len: usize) -> Option<u32> {
+    contents_len
+        .checked_add(5)
+        .and_then(|size| u32::try_from(size).ok())
 }
 
-impl RawPacket {
+impl<P: DirectedPacket> RawPacket<P> {
     /// Create a new `RawPacket` from the given bytes
-    pub(crate) fn new(bytes: Bytes) -> RawPacket {
+    pub(crate) fn new(bytes: Bytes) -> RawPacket<P> {
         debug_assert!(bytes.len() >= 5, "packet must be at least 5 bytes");
-        Self { bytes }
+        Self {
+            bytes,
+            direction: PhantomData,
+        }
     }
 
     /// Convert this `RawPacket` into the underlying `Bytes`
@@ -81,48 +111,262 @@ impl RawPacket {
         &self.bytes[5..]
     }
 
-    /// Convert this `RawPacket` to a `Packet` instance using the given
-    /// `Mappings`.
+    /// Dissect the contents of this packet into a tree of fields, annotated
+    /// with their offsets from the start of the contents (after the 5 byte
+    /// header). Unlike `to_packet`, fields decoded before an error are still
+    /// returned.
     ///
-    /// An error will be returned if no mapping exists for this type of packet
-    /// (`Error::UnmappedPacketType`) or if an error is returned by the
-    /// `Adapter` implementation for this packet type (`Error::AdapterError`).
-    pub fn to_packet(&self, mappings: &Mappings) -> Result<Packet, Error<u8>> {
+    /// An error will be returned if no mapping exists for the ID of this
+    /// packet (`Error::UnmappedPacketType`).
+    pub fn dissect(&self, mappings: &Mappings) -> Result<Dissection, Error<u8>> {
+        let typ = self
+            .packet_type(mappings)
+            .ok_or_else(|| Error::UnmappedPacketType(self.packet_id()))?;
+
+        Ok(dissect(typ, self.raw_contents()))
+    }
+
+    /// Create a borrowed view of the contents of this packet, which decodes
+    /// its fields lazily without allocating. See the `view` module of
+    /// `rotmg_packets` for the available views.
+    ///
+    /// `None` will be returned if this packet is not of the type read by the
+    /// view, including when no mapping exists for its ID. An error will be
+    /// returned if the contents of the packet are invalid
+    /// (`Error::AdapterError`).
+    pub fn view<'a, V: PacketView<'a>>(
+        &'a self,
+        mappings: &Mappings,
+    ) -> Result<Option<V>, Error<u8>> {
+        if V::PACKET_TYPE.side() != P::SENDER || self.packet_type(mappings) != Some(V::PACKET_TYPE)
+        {
+            return Ok(None);
+        }
+
+        V::from_bytes(self.raw_contents())
+            .map(Some)
+            .map_err(Error::AdapterError)
+    }
+
+    /// Convert this `RawPacket` to a packet using the given `Mappings`.
+    ///
+    /// If no mapping exists for this type of packet, `P::Unknown` will be
+    /// returned. An error will be returned if the contents of the packet could
+    /// not be decoded (`Error::DecodeError`), including when bytes are left
+    /// over after decoding.
+    pub fn to_packet(&self, mappings: &Mappings) -> Result<P, Error<u8>> {
+        self.to_packet_with(mappings, DecodeMode::Strict)
+            .map(|(packet, _)| packet)
+    }
+
+    /// Convert this `RawPacket` to a packet like `to_packet`, using the given
+    /// decode options, which may just be a `DecodeMode`. On success, the
+    /// packet is returned along with the bytes left over after decoding.
+    pub fn to_packet_with(
+        &self,
+        mappings: &Mappings,
+        options: impl Into<DecodeOptions>,
+    ) -> Result<(P, &[u8]), Error<u8>> {
         if let Some(typ) = self.packet_type(mappings) {
-            unsafe { Packet::from_bytes(typ, &mut self.raw_contents().into_buf()) }
-                .map_err(Error::AdapterError)
+            P::decode(typ, self.raw_contents(), options).map_err(Error::DecodeError)
         } else {
-            Err(Error::UnmappedPacketType(self.packet_id()))
+            Ok((self.to_unknown(), &[]))
         }
     }
 
-    /// Convert the given `Packet` into a `RawPacket` using the given
-    /// `Mappings`.
+    /// Convert this `RawPacket` to a packet like `to_packet`, but return an
+    /// unknown packet instead of an error if the contents could not be
+    /// decoded. The returned packet can always be converted back into an
+    /// identical `RawPacket`.
+    pub fn to_packet_or_unknown(&self, mappings: &Mappings) -> P {
+        self.to_packet(mappings)
+            .unwrap_or_else(|_| self.to_unknown())
+    }
+
+    /// Wrap the ID and contents of this packet in an unknown packet
+    fn to_unknown(&self) -> P {
+        let packet = Packet::Unknown {
+            id: self.packet_id(),
+            payload: self.raw_contents().to_vec(),
+        };
+
+        P::try_from(packet).unwrap_or_else(|_| unreachable!("unknown packets have no direction"))
+    }
+
+    /// Convert the given packet into a `RawPacket` using the given
+    /// `Mappings`. Unknown packets are converted using the ID they contain.
     ///
     /// An error will be returned if no mapping exists for this type of packet
-    /// (`Error::UnmappedPacketType`) or if an error is returned by the
-    /// `Adapter` implementation for this packet type (`Error::AdapterError`).
-    pub fn from_packet(
-        packet: &Packet,
+    /// (`Error::UnmappedPacketType`), if the packet is too large to be sent
+    /// (`Error::PacketTooLarge`), or if an error is returned by the `Adapter`
+    /// implementation for this packet type (`Error::AdapterError`).
+    pub fn from_packet(packet: &P, mappings: &Mappings) -> Result<Self, Error<PacketType>> {
+        Self::from_packet_with(packet, &[], mappings)
+    }
+
+    /// Convert the given packet into a `RawPacket` like `from_packet`,
+    /// appending the given bytes after its contents. This is the reverse of
+    /// `to_packet_with`, so packets decoded leniently can be modified and
+    /// encoded again without losing any bytes left over after decoding.
+    pub fn from_packet_with(
+        packet: &P,
+        trailing: &[u8],
         mappings: &Mappings,
-    ) -> Result<RawPacket, Error<PacketType>> {
-        if let Some(id) = mappings.to_game(packet.get_type()) {
-            // create a buffer, reserve enough space to fit the packet size
-            let mut buf = vec![0u8; 4];
+    ) -> Result<Self, Error<PacketType>> {
+        let id = match (packet.game_id(mappings), packet.get_type()) {
+            (Some(id), _) => id,
+            (None, Some(typ)) => return Err(Error::UnmappedPacketType(typ)),
+            (None, None) => unreachable!("unknown packets always have an id"),
+        };
 
-            // store the packet id
-            buf.push(id);
+        // check the size of the packet fits in the header before encoding it
+        let contents_len = packet.encoded_len().saturating_add(trailing.len());
+        let size = packet_size(contents_len).ok_or(Error::PacketTooLarge(contents_len))?;
 
-            // serialize the packet
-            unsafe { packet.to_bytes(&mut buf).map_err(Error::AdapterError)? };
+        // create a buffer large enough for the whole packet, starting with
+        // the header
+        let mut buf = Vec::with_capacity(size as usize);
+        buf.extend_from_slice(&size.to_be_bytes());
 
-            // go back and store the total size of the packet
-            let len = buf.len() as u32;
-            (&mut buf[..4]).copy_from_slice(&len.to_be_bytes());
+        // store the packet id
+        buf.push(id);
 
-            Ok(Self::new(buf.into()))
-        } else {
-            Err(Error::UnmappedPacketType(packet.get_type()))
+        // serialize the packet
+        packet.to_bytes(&mut buf).map_err(Error::AdapterError)?;
+        buf.extend_from_slice(trailing);
+        debug_assert_eq!(buf.len(), size as usize, "encoded_len was inaccurate");
+
+        Ok(Self::new(buf.into()))
+    }
+}
+
+impl<P: DirectedPacket> Clone for RawPacket<P> {
+    fn clone(&self) -> Self {
+        Self::new(self.bytes.clone())
+    }
+}
+
+impl<P: DirectedPacket> Debug for RawPacket<P> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("RawPacket")
+            .field("id", &self.packet_id())
+            .field("contents", &self.raw_contents())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::test_mappings;
+    use quickcheck::{quickcheck, TestResult};
+    use rotmg_packets::adapter::RLE;
+    use rotmg_packets::packets::client::Pong;
+    use rotmg_packets::packets::server::NewTick;
+    use rotmg_packets::packets::view::{NewTickView, UpdateView};
+    use rotmg_packets::packets::{ClientPacket, ServerPacket};
+
+    fn mappings() -> Mappings {
+        test_mappings(&[PacketType::Pong])
+    }
+
+    #[test]
+    fn test_unknown_round_trip() {
+        let mappings = mappings();
+        let raw = RawPacket::<ClientPacket>::new(Bytes::from(vec![0, 0, 0, 8, 200, 1, 2, 3]));
+
+        let packet = raw.to_packet(&mappings).unwrap();
+        assert_eq!(
+            packet,
+            ClientPacket::Unknown {
+                id: 200,
+                payload: vec![1, 2, 3]
+            }
+        );
+
+        let encoded = RawPacket::from_packet(&packet, &mappings).unwrap();
+        assert_eq!(encoded.into_bytes(), raw.into_bytes());
+    }
+
+    #[test]
+    fn test_packet_size() {
+        assert_eq!(packet_size(0), Some(5));
+        assert_eq!(packet_size(u32::MAX as usize - 5), Some(u32::MAX));
+        assert_eq!(packet_size(u32::MAX as usize - 4), None);
+        assert_eq!(packet_size(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_undecodable_round_trip() {
+        let mappings = mappings();
+
+        // a pong packet with a byte missing
+        let raw =
+            RawPacket::<ClientPacket>::new(Bytes::from(vec![0, 0, 0, 12, 1, 0, 0, 0, 1, 0, 0, 0]));
+        assert!(raw.to_packet(&mappings).is_err());
+
+        // the dissection points out the missing byte
+        let dissection = raw.dissect(&mappings).unwrap();
+        assert_eq!(dissection.fields.len(), 2);
+        assert_eq!(dissection.error.unwrap().path, "time");
+
+        let packet = raw.to_packet_or_unknown(&mappings);
+        let encoded = RawPacket::from_packet(&packet, &mappings).unwrap();
+        assert_eq!(encoded.into_bytes(), raw.into_bytes());
+
+        // a valid pong packet is decoded as usual
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        let raw = RawPacket::from_packet(&pong, &mappings).unwrap();
+        assert_eq!(raw.to_packet_or_unknown(&mappings), pong);
+
+        // but decoding it as a packet sent by the server fails
+        let raw = RawPacket::<ServerPacket>::new(raw.into_bytes());
+        assert!(raw.to_packet(&mappings).is_err());
+    }
+
+    #[test]
+    fn test_view() {
+        let mappings = test_mappings(&[PacketType::NewTick]);
+
+        let tick = ServerPacket::NewTick(NewTick {
+            tick_id: 3,
+            tick_time: 200,
+            statuses: RLE::new(vec![]),
+        });
+        let raw = RawPacket::from_packet(&tick, &mappings).unwrap();
+
+        let view = raw.view::<NewTickView>(&mappings).unwrap().unwrap();
+        assert_eq!(view.tick_id(), 3);
+        assert!(view.statuses().is_empty());
+        assert!(raw.view::<UpdateView>(&mappings).unwrap().is_none());
+
+        // an invalid packet can't be viewed
+        let raw = RawPacket::<ServerPacket>::new(raw.into_bytes().slice(..12));
+        assert!(raw.view::<NewTickView>(&mappings).is_err());
+    }
+
+    /// Convert a packet to a `RawPacket` and back again
+    fn round_trip<P: DirectedPacket + PartialEq>(packet: P, mappings: &Mappings) -> bool {
+        let raw = RawPacket::from_packet(&packet, mappings).unwrap();
+        raw.to_packet(mappings).unwrap() == packet
+    }
+
+    quickcheck! {
+        fn prop_packet_round_trip(packet: Packet) -> TestResult {
+            let mappings = test_mappings(PacketType::get_all_types());
+
+            if let Packet::Unknown { id, .. } = packet {
+                if mappings.to_internal(id).is_some() {
+                    return TestResult::discard();
+                }
+            }
+
+            let result = match ClientPacket::try_from(packet) {
+                Ok(packet) => round_trip(packet, &mappings),
+                Err(packet) => round_trip(ServerPacket::try_from(packet).unwrap(), &mappings),
+            };
+
+            TestResult::from_bool(result)
         }
     }
 }
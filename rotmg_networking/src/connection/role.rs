@@ -0,0 +1,51 @@
+//! Roles that one side of a connection can play, determining which packets
+//! can be sent and received over it
+
+use rotmg_packets::packets::{ClientPacket, DirectedPacket, ServerPacket, Side};
+
+/// The role played by this side of a connection.
+///
+/// Codecs and connections are parameterized by a role, so that attempting to
+/// send a packet in the wrong direction is a compile error.
+///
+/// ```compile_fail
+/// # use rotmg_networking::connection::codec::Codec;
+/// # use rotmg_networking::connection::raw_packet::RawPacket;
+/// # use rotmg_packets::mappings::Mappings;
+/// # use rotmg_packets::packets::ClientPacket;
+/// # use tokio_util::codec::Encoder;
+/// # fn send(mappings: &Mappings, packet: RawPacket<ClientPacket>) {
+/// let mut codec = Codec::new_as_server(mappings);
+/// codec.encode(packet, &mut Default::default()).unwrap();
+/// # }
+/// ```
+pub trait Role: Send + 'static {
+    /// The side of the connection this role acts as
+    const SIDE: Side;
+
+    /// The type of packets sent by this side of the connection
+    type Sends: DirectedPacket;
+
+    /// The type of packets received by this side of the connection
+    type Receives: DirectedPacket;
+}
+
+/// Acting as the server, communicating with a game client
+#[derive(Debug)]
+pub enum AsServer {}
+
+/// Acting as the client, communicating with a game server
+#[derive(Debug)]
+pub enum AsClient {}
+
+impl Role for AsServer {
+    const SIDE: Side = Side::Server;
+    type Sends = ServerPacket;
+    type Receives = ClientPacket;
+}
+
+impl Role for AsClient {
+    const SIDE: Side = Side::Client;
+    type Sends = ClientPacket;
+    type Receives = ServerPacket;
+}
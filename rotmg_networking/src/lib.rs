@@ -2,11 +2,14 @@
 //! protocol
 //!
 //! This crate provides utilities to represent ROTMG network packets, as well as
-//! code to open ROTMG client or server connections using tokio streams.
+//! code to open ROTMG client or server connections using tokio streams, to proxy
+//! connections between them, and to capture and replay the packets they send.
 
 #![deny(missing_docs)]
 #![deny(bare_trait_objects)]
 
+pub mod capture;
 pub mod connection;
 mod ext;
+pub mod proxy;
 pub mod rc4;
@@ -0,0 +1,253 @@
+//! A man-in-the-middle proxy between game clients and a game server
+//!
+//! The proxy accepts connections from clients (acting as the server), and
+//! pairs each of them with a new connection to the real server (acting as the
+//! client). Packets are forwarded in both directions, passing through `Hooks`
+//! which can inspect, modify, drop or inject packets.
+//!
+//! The `session` submodule exposes the code used to forward packets for a
+//! single pair of connections, in case you want to set up the connections
+//! yourself, and the `reconnect` submodule exposes the code used to keep
+//! clients connected to the proxy when they move between servers.
+
+pub mod reconnect;
+pub mod session;
+
+pub use self::reconnect::Reconnects;
+pub use self::session::{Hooks, Session, Verdict};
+
+use self::reconnect::ReconnectHooks;
+use self::session::run_session;
+use crate::connection::codec::CodecError;
+use crate::connection::raw_packet::RawPacket;
+use crate::connection::role::{AsClient, AsServer};
+use crate::connection::{client_listener, server_connection, Connection};
+use futures::StreamExt;
+use log::{debug, info, warn};
+use rotmg_packets::mappings::Mappings;
+use rotmg_packets::packets::ClientPacket;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::lookup_host;
+
+/// A proxy forwarding clients to a game server.
+///
+/// The type parameter `F` is a function creating the hooks for each new
+/// session.
+pub struct Proxy<F> {
+    mappings: Arc<Mappings>,
+    upstream: SocketAddr,
+    reconnects: Option<Arc<Reconnects>>,
+    new_hooks: F,
+}
+
+impl<F, H> Proxy<F>
+where
+    F: Fn(&Session) -> H + Send + Sync + 'static,
+    H: Hooks,
+{
+    /// Create a new proxy forwarding clients to the server at the given
+    /// address, using the given mappings for both legs of each connection and
+    /// creating hooks for each session with the given function
+    pub fn new(mappings: impl Into<Arc<Mappings>>, upstream: SocketAddr, new_hooks: F) -> Self {
+        Self {
+            mappings: mappings.into(),
+            upstream,
+            reconnects: None,
+            new_hooks,
+        }
+    }
+
+    /// Rewrite `Reconnect` packets sent by servers so that clients reconnect
+    /// to the proxy at the given host and port, which must be reachable by the
+    /// clients. Reconnecting clients are forwarded to the server they were
+    /// sent to rather than the initial server, including when moving between
+    /// the nexus, realms and dungeons.
+    pub fn rewrite_reconnects(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.reconnects = Some(Arc::new(Reconnects::new(host, port)));
+        self
+    }
+
+    /// Accept clients on the given address, forwarding each of them to the
+    /// server in a new task. This must be run within a tokio runtime, and
+    /// will run until an error occurs binding to the address.
+    pub async fn run(self, address: SocketAddr) -> IoResult<()> {
+        let proxy = Arc::new(self);
+        let mut listener = Box::pin(client_listener(address, proxy.mappings.clone()).await?);
+        info!("Proxying clients from {} to {}", address, proxy.upstream);
+
+        while let Some(client) = listener.next().await {
+            let client = match client {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Error accepting client: {}", e);
+                    continue;
+                }
+            };
+
+            let proxy = proxy.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.forward(client).await {
+                    warn!("Proxy session ended with error: {}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Find the server to forward a client to, given the first packet it
+    /// sent
+    async fn upstream(&self, first: &RawPacket<ClientPacket>) -> IoResult<SocketAddr> {
+        let destination = match &self.reconnects {
+            Some(reconnects) => reconnects.route_packet(first, &self.mappings),
+            None => None,
+        };
+
+        match destination {
+            Some(destination) => lookup_host((&destination.host[..], destination.port))
+                .await?
+                .next()
+                .ok_or_else(|| {
+                    let message = format!("No addresses found for {:?}", destination);
+                    IoError::new(ErrorKind::NotFound, message)
+                }),
+            None => Ok(self.upstream),
+        }
+    }
+
+    /// Forward a single client to the server
+    async fn forward(&self, mut client: Connection<AsServer>) -> Result<(), CodecError> {
+        let client_address = client.get_ref().peer_addr()?;
+
+        // wait for the first packet to decide which server to connect to
+        let first = match client.next().await {
+            Some(packet) => packet?,
+            None => return Ok(()),
+        };
+
+        let upstream = self.upstream(&first).await?;
+        debug!("Proxying client {} to {}", client_address, upstream);
+
+        let server = server_connection(upstream, self.mappings.clone()).await?;
+        let session = Session::new(self.mappings.clone(), client_address, upstream);
+        let hooks = (self.new_hooks)(&session);
+
+        match &self.reconnects {
+            Some(reconnects) => {
+                let reconnects = reconnects.clone();
+                let hooks = ReconnectHooks {
+                    inner: hooks,
+                    reconnects,
+                };
+                start_session(client, server, session, hooks, first).await
+            }
+            None => start_session(client, server, session, hooks, first).await,
+        }
+    }
+}
+
+/// Forward packets between a client and a server, starting with the first
+/// packet sent by the client
+async fn start_session<H: Hooks>(
+    client: Connection<AsServer>,
+    server: Connection<AsClient>,
+    mut session: Session,
+    mut hooks: H,
+    first: RawPacket<ClientPacket>,
+) -> Result<(), CodecError> {
+    session.client_packet(&mut hooks, first)?;
+    run_session(client, server, session, hooks).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::test_mappings;
+    use futures::SinkExt;
+    use rotmg_packets::packets::client::{Escape, Pong};
+    use rotmg_packets::packets::server::Ping;
+    use rotmg_packets::packets::{ClientPacket, PacketType, ServerPacket};
+    use std::str::FromStr;
+
+    /// Hooks which modify pongs, drop escapes, and reply to both with pings
+    struct TestHooks;
+
+    impl Hooks for TestHooks {
+        fn client_packet(
+            &mut self,
+            session: &mut Session,
+            packet: RawPacket<ClientPacket>,
+        ) -> Verdict<ClientPacket> {
+            let ping = ServerPacket::Ping(Ping { serial: 5 });
+            session.inject_to_client(&ping).unwrap();
+
+            match packet.to_packet(session.mappings()).unwrap() {
+                ClientPacket::Pong(pong) => Verdict::Replace(ClientPacket::Pong(Pong {
+                    serial: pong.serial + 10,
+                    ..pong
+                })),
+                ClientPacket::Escape(_) => Verdict::Drop,
+                _ => Verdict::Forward(packet),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let address = SocketAddr::from_str("127.0.0.1:2052").unwrap();
+        let upstream = SocketAddr::from_str("127.0.0.1:2053").unwrap();
+        let mappings = Arc::new(test_mappings(&[
+            PacketType::Ping,
+            PacketType::Pong,
+            PacketType::Escape,
+        ]));
+
+        let mut servers = Box::pin(client_listener(upstream, mappings.clone()).await.unwrap());
+        let proxy = Proxy::new(mappings.clone(), upstream, |_: &Session| TestHooks);
+        let proxy = tokio::spawn(proxy.run(address));
+
+        // let the proxy start listening before connecting to it
+        tokio::task::yield_now().await;
+
+        let mut client = server_connection(address, mappings.clone()).await.unwrap();
+        let encode = |packet| RawPacket::from_packet(&packet, &mappings).unwrap();
+
+        // escapes are dropped and pongs are modified, with pings injected
+        let escape = ClientPacket::Escape(Escape {});
+        let pong = ClientPacket::Pong(Pong { serial: 1, time: 2 });
+        client.send(encode(escape)).await.unwrap();
+        client.send(encode(pong)).await.unwrap();
+
+        // the proxy only connects to the server once the client sends data
+        let mut server = servers.next().await.unwrap().unwrap();
+        let received = server.next().await.unwrap().unwrap();
+        let expected = ClientPacket::Pong(Pong {
+            serial: 11,
+            time: 2,
+        });
+        assert_eq!(received.to_packet(&mappings).unwrap(), expected);
+
+        for _ in 0..2 {
+            let received = client.next().await.unwrap().unwrap();
+            let expected = ServerPacket::Ping(Ping { serial: 5 });
+            assert_eq!(received.to_packet(&mappings).unwrap(), expected);
+        }
+
+        // packets from the server are forwarded unchanged
+        let ping = ServerPacket::Ping(Ping { serial: 3 });
+        server
+            .send(RawPacket::from_packet(&ping, &mappings).unwrap())
+            .await
+            .unwrap();
+        let received = client.next().await.unwrap().unwrap();
+        assert_eq!(received.to_packet(&mappings).unwrap(), ping);
+
+        // closing the server closes the client
+        drop(server);
+        assert!(client.next().await.is_none());
+
+        proxy.abort();
+    }
+}
@@ -0,0 +1,438 @@
+//! Keeping clients connected to the proxy when the server sends them elsewhere
+//!
+//! When moving between the nexus, realms and dungeons, the server sends a
+//! `Reconnect` packet with the address of the next server, and the client
+//! opens a new connection to it, sending the `game_id` and `key` from the
+//! `Reconnect` in its `Hello`. `Reconnects` rewrites the address to point back
+//! to the proxy, remembering the real destination so that the new connection
+//! can be forwarded to it.
+
+use super::session::{Hooks, Session, Verdict};
+use crate::connection::raw_packet::RawPacket;
+use log::{debug, warn};
+use rotmg_packets::adapter::RLE;
+use rotmg_packets::mappings::Mappings;
+use rotmg_packets::packets::client::Hello;
+use rotmg_packets::packets::server::Reconnect;
+use rotmg_packets::packets::{ClientPacket, DecodeMode, PacketType, ServerPacket};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The port sent in a `Reconnect` when the client should reconnect to the
+/// same port (-1 as a signed integer)
+const SAME_PORT: u32 = u32::MAX;
+
+/// How long a destination is remembered by default if no client reconnects to
+/// it
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(60);
+
+/// The server a client was told to reconnect to
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Destination {
+    /// The host name or IP address of the server
+    pub host: String,
+    /// The port of the server
+    pub port: u16,
+}
+
+/// The values identifying a reconnection, sent in both the `Reconnect` and the
+/// following `Hello`
+type RouteKey = (u32, Vec<u8>);
+
+/// Rewrites `Reconnect` packets to point to the proxy, and routes the
+/// reconnecting clients to the real destination.
+///
+/// A destination is remembered until a client reconnects to it, until another
+/// `Reconnect` with the same `game_id` and `key` is sent, or until it expires.
+/// Expired destinations are removed whenever a new one is remembered.
+///
+/// Routes are only identified by the `game_id` and `key`, so clients sent to
+/// the same place share a route. In particular, every `Reconnect` to the
+/// nexus has a `game_id` of -2 and an empty key, so when several clients are
+/// sent to the nexus at once, all of them are routed to the destination of
+/// the latest `Reconnect`.
+#[derive(Debug)]
+pub struct Reconnects {
+    local: Destination,
+    expiry: Duration,
+    routes: Mutex<HashMap<RouteKey, (Destination, Instant)>>,
+}
+
+impl Reconnects {
+    /// Create a new reconnect manager, rewriting `Reconnect` packets to point
+    /// to the proxy at the given host and port
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            local: Destination {
+                host: host.into(),
+                port,
+            },
+            expiry: DEFAULT_EXPIRY,
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forget destinations which no client has reconnected to after the given
+    /// duration, instead of the default of 60 seconds
+    pub fn with_expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Rewrite a `Reconnect` packet sent by the server at the given address to
+    /// point to the proxy, remembering the real destination. An empty host or
+    /// a port of -1 refer to the server which sent the packet.
+    ///
+    /// Returns whether the packet was rewritten. Packets with an invalid port
+    /// are left unchanged.
+    pub fn rewrite(&self, reconnect: &mut Reconnect, server: SocketAddr) -> bool {
+        let host = if reconnect.host.is_empty() {
+            server.ip().to_string()
+        } else {
+            reconnect.host.to_string()
+        };
+
+        let port = match reconnect.port {
+            SAME_PORT => server.port(),
+            port => match u16::try_from(port) {
+                Ok(port) => port,
+                Err(_) => {
+                    warn!("Not rewriting reconnect with invalid port {}", port);
+                    return false;
+                }
+            },
+        };
+
+        let destination = Destination { host, port };
+        debug!("Rewriting reconnect to {:?}", destination);
+
+        let key = (reconnect.game_id, reconnect.key.to_vec());
+        let mut routes = self.routes.lock().unwrap();
+        routes.retain(|_, (_, created)| created.elapsed() < self.expiry);
+        routes.insert(key, (destination, Instant::now()));
+
+        reconnect.host = RLE::new(self.local.host.clone());
+        reconnect.port = u32::from(self.local.port);
+        true
+    }
+
+    /// Get the destination for a client sending the given `Hello`, or `None`
+    /// if it isn't reconnecting through the proxy
+    pub fn route(&self, hello: &Hello) -> Option<Destination> {
+        let key = (hello.game_id, hello.key.to_vec());
+        let (destination, created) = self.routes.lock().unwrap().remove(&key)?;
+
+        if created.elapsed() < self.expiry {
+            Some(destination)
+        } else {
+            None
+        }
+    }
+
+    /// Get the destination for a client sending the given packet as the first
+    /// packet of its connection, or `None` if it isn't a `Hello` from a client
+    /// reconnecting through the proxy. The `Hello` is decoded leniently, since
+    /// only the `game_id` and `key` are needed.
+    pub fn route_packet(
+        &self,
+        packet: &RawPacket<ClientPacket>,
+        mappings: &Mappings,
+    ) -> Option<Destination> {
+        if packet.packet_type(mappings) != Some(PacketType::Hello) {
+            return None;
+        }
+
+        match packet.to_packet_with(mappings, DecodeMode::Lenient) {
+            Ok((ClientPacket::Hello(hello), _)) => self.route(&hello),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Error decoding hello: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Hooks wrapping other hooks, rewriting `Reconnect` packets after they've
+/// been handled by the inner hooks. Forwarded packets are decoded leniently,
+/// and any bytes left over are kept at the end of the rewritten packet.
+pub(crate) struct ReconnectHooks<H> {
+    pub inner: H,
+    pub reconnects: Arc<Reconnects>,
+}
+
+impl<H: Hooks> Hooks for ReconnectHooks<H> {
+    fn client_packet(
+        &mut self,
+        session: &mut Session,
+        packet: RawPacket<ClientPacket>,
+    ) -> Verdict<ClientPacket> {
+        self.inner.client_packet(session, packet)
+    }
+
+    fn server_packet(
+        &mut self,
+        session: &mut Session,
+        packet: RawPacket<ServerPacket>,
+    ) -> Verdict<ServerPacket> {
+        let raw = match self.inner.server_packet(session, packet) {
+            Verdict::Forward(raw) => raw,
+            Verdict::Replace(mut packet) => {
+                if let ServerPacket::Reconnect(reconnect) = &mut packet {
+                    self.reconnects.rewrite(reconnect, session.server_address());
+                }
+                return Verdict::Replace(packet);
+            }
+            Verdict::Drop => return Verdict::Drop,
+        };
+
+        if raw.packet_type(session.mappings()) != Some(PacketType::Reconnect) {
+            return Verdict::Forward(raw);
+        }
+
+        let (mut packet, trailing) =
+            match raw.to_packet_with(session.mappings(), DecodeMode::Lenient) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    warn!("Error decoding reconnect: {}", e);
+                    return Verdict::Forward(raw);
+                }
+            };
+
+        let rewritten = match &mut packet {
+            ServerPacket::Reconnect(reconnect) => {
+                self.reconnects.rewrite(reconnect, session.server_address())
+            }
+            _ => false,
+        };
+        if !rewritten {
+            return Verdict::Forward(raw);
+        }
+
+        match RawPacket::from_packet_with(&packet, trailing, session.mappings()) {
+            Ok(rewritten) => Verdict::Forward(rewritten),
+            Err(e) => {
+                warn!("Error encoding reconnect: {}", e);
+                Verdict::Forward(raw)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{client_listener, server_connection, test_mappings};
+    use crate::proxy::Proxy;
+    use futures::{SinkExt, StreamExt};
+    use rotmg_packets::packets::DirectedPacket;
+    use std::str::FromStr;
+
+    fn reconnect(host: &str, port: u32) -> Reconnect {
+        Reconnect {
+            name: RLE::new("Realm".to_owned()),
+            host: RLE::new(host.to_owned()),
+            stats: RLE::new(String::new()),
+            port,
+            game_id: 7,
+            key_time: 0,
+            is_from_arena: false,
+            key: RLE::new(vec![1, 2, 3]),
+        }
+    }
+
+    fn hello(game_id: u32, key: Vec<u8>) -> Hello {
+        Hello {
+            build_version: RLE::new("1.0".to_owned()),
+            game_id,
+            guid: RLE::new(String::new()),
+            rand1: 0,
+            password: RLE::new(String::new()),
+            rand2: 0,
+            secret: RLE::new(String::new()),
+            key_time: 0,
+            key: RLE::new(key),
+            map_json: RLE::new(String::new()),
+            entry_tag: RLE::new(String::new()),
+            game_net: RLE::new(String::new()),
+            game_net_user_id: RLE::new(String::new()),
+            play_platform: RLE::new(String::new()),
+            platform_token: RLE::new(String::new()),
+            user_token: RLE::new(String::new()),
+        }
+    }
+
+    #[test]
+    fn test_reconnects() {
+        let reconnects = Reconnects::new("127.0.0.1", 2050);
+        let server = SocketAddr::from_str("10.0.0.1:2050").unwrap();
+
+        // reconnects are rewritten to point to the proxy
+        let mut packet = reconnect("10.0.0.2", 2051);
+        assert!(reconnects.rewrite(&mut packet, server));
+        assert_eq!(&packet.host[..], "127.0.0.1");
+        assert_eq!(packet.port, 2050);
+
+        // unrelated clients aren't routed
+        assert_eq!(reconnects.route(&hello(7, vec![])), None);
+        assert_eq!(reconnects.route(&hello(8, vec![1, 2, 3])), None);
+
+        // and the client is routed to the real destination once
+        let destination = Destination {
+            host: "10.0.0.2".to_owned(),
+            port: 2051,
+        };
+        assert_eq!(
+            reconnects.route(&hello(7, vec![1, 2, 3])),
+            Some(destination)
+        );
+        assert_eq!(reconnects.route(&hello(7, vec![1, 2, 3])), None);
+
+        // an empty host and port refer to the server sending the reconnect
+        reconnects.rewrite(&mut reconnect("", SAME_PORT), server);
+        let destination = Destination {
+            host: "10.0.0.1".to_owned(),
+            port: 2050,
+        };
+        assert_eq!(
+            reconnects.route(&hello(7, vec![1, 2, 3])),
+            Some(destination)
+        );
+    }
+
+    #[test]
+    fn test_invalid_reconnects() {
+        let reconnects = Reconnects::new("127.0.0.1", 2050);
+        let server = SocketAddr::from_str("10.0.0.1:2050").unwrap();
+
+        // reconnects with a port which doesn't fit in a u16 are left alone
+        let mut packet = reconnect("10.0.0.2", 0x1_0803);
+        assert!(!reconnects.rewrite(&mut packet, server));
+        assert_eq!(packet, reconnect("10.0.0.2", 0x1_0803));
+        assert_eq!(reconnects.route(&hello(7, vec![1, 2, 3])), None);
+    }
+
+    #[test]
+    fn test_reconnect_expiry() {
+        let reconnects = Reconnects::new("127.0.0.1", 2050).with_expiry(Duration::from_millis(20));
+        let server = SocketAddr::from_str("10.0.0.1:2050").unwrap();
+
+        let mut stale = reconnect("10.0.0.2", 2051);
+        stale.game_id = 8;
+        reconnects.rewrite(&mut stale, server);
+        reconnects.rewrite(&mut reconnect("10.0.0.2", 2051), server);
+        std::thread::sleep(Duration::from_millis(30));
+
+        // expired routes aren't used...
+        assert_eq!(reconnects.route(&hello(7, vec![1, 2, 3])), None);
+
+        // ...and are removed when a new route is added
+        reconnects.rewrite(&mut reconnect("10.0.0.3", 2051), server);
+        let routes = reconnects.routes.lock().unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[&(7, vec![1, 2, 3])].0.host, "10.0.0.3");
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        let mappings = Arc::new(test_mappings(&[PacketType::Hello, PacketType::Reconnect]));
+        let client = SocketAddr::from_str("127.0.0.1:3000").unwrap();
+        let server = SocketAddr::from_str("10.0.0.1:2050").unwrap();
+        let mut session = Session::new(mappings.clone(), client, server);
+        let reconnects = Arc::new(Reconnects::new("127.0.0.1", 2050));
+        let mut hooks = ReconnectHooks {
+            inner: (),
+            reconnects: reconnects.clone(),
+        };
+
+        // reconnects with fields added to the end are still rewritten, keeping
+        // the extra bytes
+        let packet = ServerPacket::Reconnect(reconnect("10.0.0.2", 2051));
+        let raw = RawPacket::from_packet_with(&packet, &[9, 9], &mappings).unwrap();
+        let raw = match hooks.server_packet(&mut session, raw) {
+            Verdict::Forward(raw) => raw,
+            _ => panic!("reconnect wasn't forwarded"),
+        };
+        let (packet, trailing) = raw.to_packet_with(&mappings, DecodeMode::Lenient).unwrap();
+        assert_eq!(
+            packet,
+            ServerPacket::Reconnect(reconnect("127.0.0.1", 2050))
+        );
+        assert_eq!(trailing, &[9, 9]);
+
+        // and so are hellos with extra fields
+        let packet = ClientPacket::Hello(hello(7, vec![1, 2, 3]));
+        let raw = RawPacket::from_packet_with(&packet, &[9], &mappings).unwrap();
+        let destination = Destination {
+            host: "10.0.0.2".to_owned(),
+            port: 2051,
+        };
+        assert_eq!(reconnects.route_packet(&raw, &mappings), Some(destination));
+    }
+
+    fn encode<P: DirectedPacket>(mappings: &Mappings, packet: P) -> RawPacket<P> {
+        RawPacket::from_packet(&packet, mappings).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_proxy_reconnects() {
+        let address = SocketAddr::from_str("127.0.0.1:2054").unwrap();
+        let nexus = SocketAddr::from_str("127.0.0.1:2055").unwrap();
+        let realm = SocketAddr::from_str("127.0.0.1:2056").unwrap();
+        let mappings = Arc::new(test_mappings(&[PacketType::Hello, PacketType::Reconnect]));
+
+        let mut nexus_clients = Box::pin(client_listener(nexus, mappings.clone()).await.unwrap());
+        let mut realm_clients = Box::pin(client_listener(realm, mappings.clone()).await.unwrap());
+        let proxy = Proxy::new(mappings.clone(), nexus, |_: &Session| ())
+            .rewrite_reconnects("localhost", address.port());
+        let proxy = tokio::spawn(proxy.run(address));
+        tokio::task::yield_now().await;
+
+        // new clients are sent to the nexus...
+        let mut client = server_connection(address, mappings.clone()).await.unwrap();
+        client
+            .send(encode(&mappings, ClientPacket::Hello(hello(0, vec![]))))
+            .await
+            .unwrap();
+        let mut server = nexus_clients.next().await.unwrap().unwrap();
+        server.next().await.unwrap().unwrap();
+
+        // ...which sends them to the realm on the same host, through the proxy
+        let packet = ServerPacket::Reconnect(reconnect("", realm.port().into()));
+        server.send(encode(&mappings, packet)).await.unwrap();
+        let received = client.next().await.unwrap().unwrap();
+        let mut expected = reconnect("localhost", address.port().into());
+        match received.to_packet(&mappings).unwrap() {
+            ServerPacket::Reconnect(reconnect) => assert_eq!(reconnect, expected),
+            other => panic!("unexpected packet: {:?}", other),
+        }
+
+        // reconnecting clients are sent to the realm
+        let mut client = server_connection(address, mappings.clone()).await.unwrap();
+        let packet = ClientPacket::Hello(hello(expected.game_id, expected.key.to_vec()));
+        client
+            .send(encode(&mappings, packet.clone()))
+            .await
+            .unwrap();
+        let mut server = realm_clients.next().await.unwrap().unwrap();
+        let received = server.next().await.unwrap().unwrap();
+        assert_eq!(received.to_packet(&mappings).unwrap(), packet);
+
+        // reconnects sent by the realm are rewritten as well
+        expected.port = 2057;
+        server
+            .send(encode(&mappings, ServerPacket::Reconnect(expected)))
+            .await
+            .unwrap();
+        let received = client.next().await.unwrap().unwrap();
+        match received.to_packet(&mappings).unwrap() {
+            ServerPacket::Reconnect(reconnect) => assert_eq!(reconnect.port, 2054),
+            other => panic!("unexpected packet: {:?}", other),
+        }
+
+        proxy.abort();
+    }
+}
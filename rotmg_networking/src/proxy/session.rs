@@ -0,0 +1,218 @@
+//! Forwarding packets between the two legs of a proxied connection
+
+use crate::connection::codec::CodecError;
+use crate::connection::raw_packet::{Error as RawPacketError, RawPacket};
+use crate::connection::role::{AsClient, AsServer};
+use crate::connection::Connection;
+use futures::{SinkExt, StreamExt};
+use rotmg_packets::mappings::Mappings;
+use rotmg_packets::packets::{ClientPacket, DirectedPacket, PacketType, ServerPacket};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// What to do with a packet intercepted by a hook
+#[derive(Debug)]
+pub enum Verdict<P: DirectedPacket> {
+    /// Forward the given raw packet, which may be the original packet or a
+    /// modified one
+    Forward(RawPacket<P>),
+
+    /// Forward the given packet in place of the original, encoding it with the
+    /// mappings of the session
+    Replace(P),
+
+    /// Drop the packet without forwarding it
+    Drop,
+}
+
+/// Hooks called for every packet passing through a proxied connection, which
+/// may inspect, modify, drop or inject packets in either direction.
+///
+/// Each leg of the connection is encrypted with its own RC4 ciphers, so
+/// dropping or injecting packets never desynchronizes either side. By default,
+/// all packets are forwarded unchanged.
+pub trait Hooks: Send + 'static {
+    /// Handle a packet sent by the client to the server
+    fn client_packet(
+        &mut self,
+        _session: &mut Session,
+        packet: RawPacket<ClientPacket>,
+    ) -> Verdict<ClientPacket> {
+        Verdict::Forward(packet)
+    }
+
+    /// Handle a packet sent by the server to the client
+    fn server_packet(
+        &mut self,
+        _session: &mut Session,
+        packet: RawPacket<ServerPacket>,
+    ) -> Verdict<ServerPacket> {
+        Verdict::Forward(packet)
+    }
+}
+
+/// Hooks which forward all packets unchanged
+impl Hooks for () {}
+
+/// The state of a proxied connection, passed to hooks
+#[derive(Debug)]
+pub struct Session {
+    mappings: Arc<Mappings>,
+    client_address: SocketAddr,
+    server_address: SocketAddr,
+    to_client: Vec<RawPacket<ServerPacket>>,
+    to_server: Vec<RawPacket<ClientPacket>>,
+}
+
+impl Session {
+    /// Create a new session between a client and a server with the given
+    /// addresses, using the given mappings to encode packets
+    pub fn new(
+        mappings: impl Into<Arc<Mappings>>,
+        client_address: SocketAddr,
+        server_address: SocketAddr,
+    ) -> Self {
+        Self {
+            mappings: mappings.into(),
+            client_address,
+            server_address,
+            to_client: vec![],
+            to_server: vec![],
+        }
+    }
+
+    /// Get the mappings used by this session
+    pub fn mappings(&self) -> &Arc<Mappings> {
+        &self.mappings
+    }
+
+    /// Get the address of the client
+    pub fn client_address(&self) -> SocketAddr {
+        self.client_address
+    }
+
+    /// Get the address of the server
+    pub fn server_address(&self) -> SocketAddr {
+        self.server_address
+    }
+
+    /// Send a packet to the client, after the packet currently being handled
+    pub fn inject_to_client(
+        &mut self,
+        packet: &ServerPacket,
+    ) -> Result<(), RawPacketError<PacketType>> {
+        let raw = RawPacket::from_packet(packet, &self.mappings)?;
+        self.to_client.push(raw);
+        Ok(())
+    }
+
+    /// Send a raw packet to the client, after the packet currently being
+    /// handled
+    pub fn inject_raw_to_client(&mut self, packet: RawPacket<ServerPacket>) {
+        self.to_client.push(packet);
+    }
+
+    /// Send a packet to the server, after the packet currently being handled
+    pub fn inject_to_server(
+        &mut self,
+        packet: &ClientPacket,
+    ) -> Result<(), RawPacketError<PacketType>> {
+        let raw = RawPacket::from_packet(packet, &self.mappings)?;
+        self.to_server.push(raw);
+        Ok(())
+    }
+
+    /// Send a raw packet to the server, after the packet currently being
+    /// handled
+    pub fn inject_raw_to_server(&mut self, packet: RawPacket<ClientPacket>) {
+        self.to_server.push(packet);
+    }
+
+    /// Convert a verdict to the raw packet to forward, if any
+    fn resolve<P: DirectedPacket>(
+        &self,
+        verdict: Verdict<P>,
+    ) -> Result<Option<RawPacket<P>>, CodecError> {
+        match verdict {
+            Verdict::Forward(raw) => Ok(Some(raw)),
+            Verdict::Replace(packet) => RawPacket::from_packet(&packet, &self.mappings)
+                .map(Some)
+                .map_err(CodecError::PacketError),
+            Verdict::Drop => Ok(None),
+        }
+    }
+
+    /// Pass a packet sent by the client through the given hooks, queueing it
+    /// to be sent to the server ahead of any packets injected by the hooks
+    pub(crate) fn client_packet<H: Hooks>(
+        &mut self,
+        hooks: &mut H,
+        packet: RawPacket<ClientPacket>,
+    ) -> Result<(), CodecError> {
+        let position = self.to_server.len();
+        let verdict = hooks.client_packet(self, packet);
+
+        if let Some(raw) = self.resolve(verdict)? {
+            self.to_server.insert(position, raw);
+        }
+
+        Ok(())
+    }
+
+    /// Pass a packet sent by the server through the given hooks, queueing it
+    /// to be sent to the client ahead of any packets injected by the hooks
+    pub(crate) fn server_packet<H: Hooks>(
+        &mut self,
+        hooks: &mut H,
+        packet: RawPacket<ServerPacket>,
+    ) -> Result<(), CodecError> {
+        let position = self.to_client.len();
+        let verdict = hooks.server_packet(self, packet);
+
+        if let Some(raw) = self.resolve(verdict)? {
+            self.to_client.insert(position, raw);
+        }
+
+        Ok(())
+    }
+}
+
+/// Forward packets between a client and a server until either side closes
+/// its connection, passing each packet through the given hooks.
+///
+/// The connection to the client acts as the server, and the connection to the
+/// server acts as the client, so that each leg has its own RC4 state. When
+/// either connection is closed, the other is closed as well.
+pub async fn run_session<H: Hooks>(
+    mut client: Connection<AsServer>,
+    mut server: Connection<AsClient>,
+    mut session: Session,
+    mut hooks: H,
+) -> Result<(), CodecError> {
+    loop {
+        // send any queued packets before waiting for the next one
+        for raw in session.to_server.drain(..) {
+            server.feed(raw).await?;
+        }
+        for raw in session.to_client.drain(..) {
+            client.feed(raw).await?;
+        }
+
+        server.flush().await?;
+        client.flush().await?;
+
+        tokio::select! {
+            packet = client.next() => match packet {
+                Some(packet) => session.client_packet(&mut hooks, packet?)?,
+                None => break,
+            },
+            packet = server.next() => match packet {
+                Some(packet) => session.server_packet(&mut hooks, packet?)?,
+                None => break,
+            },
+        }
+    }
+
+    client.close().await?;
+    server.close().await
+}
@@ -0,0 +1,137 @@
+//! Compare decoding large tick packets into owned values against reading them
+//! through borrowed views, and decoding through a generic buffer against a
+//! `dyn Buf` trait object, and measure decoding large byte lists
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rotmg_packets::adapter::{Adapter, Buf, RLE};
+use rotmg_packets::packets::data::*;
+use rotmg_packets::packets::server::{NewTick, Pic, Update};
+use rotmg_packets::packets::view::{NewTickView, UpdateView, View};
+use rotmg_packets::packets::{Packet, PacketType, Side};
+
+/// The status of an object with a typical mix of integer and string stats
+fn status(id: u32) -> ObjectStatusData {
+    let mut stats = (0..20)
+        .map(|i| StatData::Integer(StatType::HP_STAT, i))
+        .collect::<Vec<_>>();
+    stats.push(StatData::String(
+        StatType::NAME_STAT,
+        format!("Player{}", id),
+    ));
+    stats.push(StatData::String(
+        StatType::GUILD_NAME_STAT,
+        "Guild".to_owned(),
+    ));
+
+    ObjectStatusData {
+        object_id: id,
+        pos: WorldPosData { x: 10.5, y: 20.5 },
+        stats: RLE::new(stats),
+    }
+}
+
+/// Encode a packet into its binary contents
+fn encode<T: Adapter>(packet: &T) -> Vec<u8> {
+    let mut contents = vec![];
+    packet.put_be(&mut contents).unwrap();
+    contents
+}
+
+fn new_tick(c: &mut Criterion) {
+    let tick = NewTick {
+        tick_id: 1,
+        tick_time: 200,
+        statuses: RLE::new((0..100).map(status).collect()),
+    };
+    let contents = encode(&tick);
+
+    let mut group = c.benchmark_group("NewTick");
+    group.bench_function("owned", |b| {
+        b.iter(|| Packet::from_bytes(PacketType::NewTick, Side::Server, black_box(&contents)))
+    });
+    group.bench_function("owned generic", |b| {
+        b.iter(|| NewTick::get_be(&mut black_box(&contents[..])))
+    });
+    group.bench_function("owned dyn Buf", |b| {
+        b.iter(|| {
+            let buf: &mut dyn Buf = &mut black_box(&contents[..]);
+            NewTick::get_be(buf)
+        })
+    });
+    group.bench_function("view", |b| {
+        b.iter(|| NewTickView::from_bytes(black_box(&contents)))
+    });
+    group.bench_function("view positions", |b| {
+        b.iter(|| {
+            let view = NewTickView::from_bytes(black_box(&contents)).unwrap();
+            view.statuses()
+                .iter()
+                .map(|s| s.pos().x + s.pos().y)
+                .sum::<f32>()
+        })
+    });
+    group.finish();
+}
+
+fn update(c: &mut Criterion) {
+    let update = Update {
+        tiles: RLE::new(
+            (0..500)
+                .map(|i| GroundTileData {
+                    x: i % 20,
+                    y: i / 20,
+                    tile: 0x70,
+                })
+                .collect(),
+        ),
+        new_objs: RLE::new(
+            (0..100)
+                .map(|i| ObjectData {
+                    object_type: 0x0300,
+                    status: status(i),
+                })
+                .collect(),
+        ),
+        drops: RLE::new((100..150).collect()),
+    };
+    let contents = encode(&update);
+
+    let mut group = c.benchmark_group("Update");
+    group.bench_function("owned", |b| {
+        b.iter(|| Packet::from_bytes(PacketType::Update, Side::Server, black_box(&contents)))
+    });
+    group.bench_function("view", |b| {
+        b.iter(|| UpdateView::from_bytes(black_box(&contents)))
+    });
+    group.bench_function("view names", |b| {
+        b.iter(|| {
+            let view = UpdateView::from_bytes(black_box(&contents)).unwrap();
+            view.new_objs()
+                .iter()
+                .filter_map(|o| o.status().stat(StatType::NAME_STAT))
+                .filter_map(|s| s.as_str())
+                .map(str::len)
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+fn pic(c: &mut Criterion) {
+    let pic = Pic {
+        w: 256,
+        h: 256,
+        bitmap_data: (0..256 * 256 * 4).map(|i| i as u8).collect(),
+    };
+    let contents = encode(&pic);
+
+    let mut group = c.benchmark_group("Pic");
+    group.bench_function("decode", |b| {
+        b.iter(|| Pic::get_be(&mut black_box(&contents[..])))
+    });
+    group.bench_function("encode", |b| b.iter(|| encode(black_box(&pic))));
+    group.finish();
+}
+
+criterion_group!(benches, new_tick, update, pic);
+criterion_main!(benches);
@@ -1,34 +1,113 @@
 //! Implementations of `Adapter` for non-primitive standard types
 
 use super::{Adapter, Result};
+use crate::packets::DecodeOptions;
+use crate::prelude::*;
 use bytes::{Buf, BufMut};
+use core::convert::TryInto;
 
 /// Will only attempt to deserialize when bytes are remaining in the buffer
 /// Will only serialize when `Some(T)` is passed
+/// Errors keep the path of the inner value, since no index or field is added
 impl<T: Adapter> Adapter for Option<T> {
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self>
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self>
     where
         Self: Sized,
     {
+        Self::get_be_with(bytes, &DecodeOptions::default())
+    }
+
+    fn get_be_with<B: Buf + ?Sized>(bytes: &mut B, options: &DecodeOptions) -> Result<Self> {
         if bytes.has_remaining() {
-            T::get_be(bytes).map(Some)
+            T::get_be_with(bytes, options).map(Some)
         } else {
             Ok(None)
         }
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
         match self {
             Some(v) => v.put_be(buffer),
             None => Ok(()),
         }
     }
+
+    fn encoded_len(&self) -> usize {
+        self.as_ref().map_or(0, T::encoded_len)
+    }
+}
+
+/// Fixed-length arrays are encoded as consecutive items with no length prefix
+impl<T: Adapter, const N: usize> Adapter for [T; N] {
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+        Self::get_be_with(bytes, &DecodeOptions::default())
+    }
+
+    fn get_be_with<B: Buf + ?Sized>(bytes: &mut B, options: &DecodeOptions) -> Result<Self> {
+        let items = (0..N)
+            .map(|i| T::get_be_with(bytes, options).map_err(|e| e.at_index(i, bytes.remaining())))
+            .collect::<Result<Vec<T>>>()?;
+
+        Ok(items.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+        self.iter().try_for_each(|i| i.put_be(buffer))
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.iter().map(T::encoded_len).sum()
+    }
+}
+
+// tuples are encoded as each of their elements in order
+macro_rules! tuple_adapter {
+    ($( ( $( $name:ident : $index:tt ),* ) ),* $(,)?) => {
+        $(
+            impl<$( $name: Adapter ),*> Adapter for ($( $name, )*) {
+                fn get_be<Buffer: Buf + ?Sized>(bytes: &mut Buffer) -> Result<Self> {
+                    Self::get_be_with(bytes, &DecodeOptions::default())
+                }
+
+                fn get_be_with<Buffer: Buf + ?Sized>(
+                    bytes: &mut Buffer,
+                    options: &DecodeOptions,
+                ) -> Result<Self> {
+                    Ok(($(
+                        $name::get_be_with(bytes, options)
+                            .map_err(|e| e.in_field(stringify!($index), bytes.remaining()))?,
+                    )*))
+                }
+
+                fn put_be<Buffer: BufMut + ?Sized>(&self, buffer: &mut Buffer) -> Result<()> {
+                    $( self.$index.put_be(buffer)?; )*
+                    Ok(())
+                }
+
+                fn encoded_len(&self) -> usize {
+                    0 $( + self.$index.encoded_len() )*
+                }
+            }
+        )*
+    };
+}
+
+tuple_adapter! {
+    (A: 0),
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3),
+    (A: 0, B: 1, C: 2, D: 3, E: 4),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7),
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::adapter::Adapter;
-    use bytes::IntoBuf;
+    use crate::adapter::{Adapter, Error};
+    use crate::prelude::*;
+    use assert_matches::assert_matches;
 
     #[test]
     fn test_primitive_adapters() {
@@ -40,9 +119,38 @@ mod tests {
 
         // read some data
 
-        let mut reader = buffer.into_buf();
+        let mut reader = &buffer[..];
 
         assert_eq!(Some(42i32), Adapter::get_be(&mut reader).unwrap());
         assert_eq!(None::<i32>, Adapter::get_be(&mut reader).unwrap());
     }
+
+    #[test]
+    fn test_array_adapters() {
+        let mut buf = vec![];
+        [1u16, 2, 3].put_be(&mut buf).expect("encoding error");
+        assert_eq!(buf, vec![0, 1, 0, 2, 0, 3]);
+        assert_eq!([1u16, 2, 3].encoded_len(), 6);
+
+        let output = <[u16; 3]>::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(output, [1, 2, 3]);
+
+        let error = <[u16; 4]>::get_be(&mut &buf[..]).unwrap_err();
+        assert_matches!(error.root(), Error::InsufficientBytes { .. });
+        assert_eq!(error.path(), Some("[3]"));
+    }
+
+    #[test]
+    fn test_tuple_adapters() {
+        let mut buf = vec![];
+        (1u8, 2u16, true).put_be(&mut buf).expect("encoding error");
+        assert_eq!(buf, vec![1, 0, 2, 1]);
+        assert_eq!((1u8, 2u16, true).encoded_len(), 4);
+
+        let output = <(u8, u16, bool)>::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(output, (1, 2, true));
+
+        let error = <(u8, u16, bool)>::get_be(&mut &buf[..2]).unwrap_err();
+        assert_eq!(error.path(), Some("1"));
+    }
 }
@@ -0,0 +1,249 @@
+//! Implementation of `Adapter` for variable-length compressed integers
+
+use super::{Adapter, Error, Result, RLE};
+use crate::prelude::*;
+use bytes::{Buf, BufMut};
+use core::convert::TryFrom;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use num::{Bounded, FromPrimitive, ToPrimitive};
+#[cfg(feature = "quickcheck")]
+use quickcheck::{Arbitrary, Gen};
+use serde::{Deserialize, Serialize};
+
+/// Set on every byte except the last
+const CONTINUE_BIT: u8 = 0x80;
+
+/// Set on the first byte when the value is negative
+const SIGN_BIT: u8 = 0x40;
+
+/// The maximum number of bytes a compressed 32-bit integer can take up
+const MAX_BYTES: usize = 5;
+
+/// A signed 32-bit integer encoded using a variable number of bytes.
+///
+/// The first byte holds the lowest 6 bits of the magnitude, along with a sign
+/// bit (`0x40`). Each following byte holds the next 7 bits. Every byte except
+/// the last has its highest bit (`0x80`) set. Small values therefore take up a
+/// single byte, while the largest take up five.
+///
+/// `CompressedInt` can also be used as the length prefix of an `RLE`, see
+/// `CompressedRLE`.
+///
+/// # Examples
+///
+/// ```
+/// # use rotmg_packets::adapter::{Adapter, CompressedInt};
+/// let mut encoded = vec![];
+/// CompressedInt(-100).put_be(&mut encoded).unwrap();
+///
+/// assert_eq!(encoded, vec![0xe4, 0x01]);
+/// ```
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct CompressedInt(pub i32);
+
+/// A run-length encoded value with a compressed length prefix
+pub type CompressedRLE<T> = RLE<T, CompressedInt>;
+
+impl Adapter for CompressedInt {
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+        let first = u8::get_be(bytes)?;
+        let negative = first & SIGN_BIT != 0;
+
+        let mut magnitude = u64::from(first & 0x3f);
+        let mut more = first & CONTINUE_BIT != 0;
+        let mut shift = 6;
+
+        for _ in 1..MAX_BYTES {
+            if !more {
+                break;
+            }
+
+            let byte = u8::get_be(bytes)?;
+            magnitude |= u64::from(byte & 0x7f) << shift;
+            more = byte & CONTINUE_BIT != 0;
+            shift += 7;
+        }
+
+        if more {
+            return Err(Error::InvalidData(format!(
+                "compressed int longer than {} bytes",
+                MAX_BYTES
+            )));
+        }
+
+        let value = if negative {
+            -(magnitude as i64)
+        } else {
+            magnitude as i64
+        };
+
+        i32::try_from(value)
+            .map(CompressedInt)
+            .map_err(|_| Error::InvalidData(format!("compressed int out of range: {}", value)))
+    }
+
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+        let mut magnitude = self.0.unsigned_abs();
+
+        let mut byte = (magnitude & 0x3f) as u8;
+        if self.0 < 0 {
+            byte |= SIGN_BIT;
+        }
+        magnitude >>= 6;
+
+        loop {
+            if magnitude != 0 {
+                byte |= CONTINUE_BIT;
+            }
+
+            buffer.put_u8(byte);
+
+            if magnitude == 0 {
+                return Ok(());
+            }
+
+            byte = (magnitude & 0x7f) as u8;
+            magnitude >>= 7;
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut magnitude = self.0.unsigned_abs() >> 6;
+        let mut len = 1;
+
+        while magnitude != 0 {
+            magnitude >>= 7;
+            len += 1;
+        }
+
+        len
+    }
+}
+
+impl From<i32> for CompressedInt {
+    fn from(value: i32) -> Self {
+        CompressedInt(value)
+    }
+}
+
+impl From<CompressedInt> for i32 {
+    fn from(value: CompressedInt) -> Self {
+        value.0
+    }
+}
+
+impl Display for CompressedInt {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+// the following allow CompressedInt to be used as the length of an RLE
+
+impl ToPrimitive for CompressedInt {
+    fn to_i64(&self) -> Option<i64> {
+        Some(i64::from(self.0))
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.0.to_u64()
+    }
+}
+
+impl FromPrimitive for CompressedInt {
+    fn from_i64(n: i64) -> Option<Self> {
+        i32::from_i64(n).map(CompressedInt)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        i32::from_u64(n).map(CompressedInt)
+    }
+}
+
+impl Bounded for CompressedInt {
+    fn min_value() -> Self {
+        CompressedInt(i32::MIN)
+    }
+
+    fn max_value() -> Self {
+        CompressedInt(i32::MAX)
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+impl Arbitrary for CompressedInt {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        CompressedInt(i32::arbitrary(g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn test_compressed_int() {
+        let values = [0, 1, 63, 64, -64, 8191, 8192, i32::MAX, i32::MIN];
+        let encodings: [&[u8]; 9] = [
+            &[0x00],
+            &[0x01],
+            &[0x3f],
+            &[0x80, 0x01],
+            &[0xc0, 0x01],
+            &[0xbf, 0x7f],
+            &[0x80, 0x80, 0x01],
+            &[0xbf, 0xff, 0xff, 0xff, 0x0f],
+            &[0xc0, 0x80, 0x80, 0x80, 0x10],
+        ];
+
+        for (&value, &expected) in values.iter().zip(encodings.iter()) {
+            let mut buf = vec![];
+            CompressedInt(value)
+                .put_be(&mut buf)
+                .expect("encoding error");
+            assert_eq!(buf, expected);
+            assert_eq!(CompressedInt(value).encoded_len(), expected.len());
+
+            let output = CompressedInt::get_be(&mut &buf[..]).expect("decoding error");
+            assert_eq!(output, CompressedInt(value));
+        }
+
+        assert_matches!(
+            CompressedInt::get_be(&mut &[0xff; 6][..]),
+            Err(Error::InvalidData(_))
+        );
+    }
+
+    #[test]
+    fn test_compressed_rle_vec() {
+        let mut buf = vec![];
+        CompressedRLE::<Vec<u8>>::new(vec![1, 2, 3, 4, 5])
+            .put_be(&mut buf)
+            .expect("encoding error");
+        assert_eq!(buf, vec![5, 1, 2, 3, 4, 5]);
+
+        let output = CompressedRLE::<Vec<u8>>::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(output.unwrap(), vec![1, 2, 3, 4, 5]);
+
+        let large = vec![0u8; 100];
+        buf.clear();
+        CompressedRLE::new(large.clone())
+            .put_be(&mut buf)
+            .expect("encoding error");
+        assert_eq!(&buf[..2], &[0xa4, 0x01]);
+        assert_eq!(buf.len(), 102);
+
+        let output = CompressedRLE::<Vec<u8>>::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(output.unwrap(), large);
+
+        // negative lengths are invalid
+        assert_matches!(
+            CompressedRLE::<Vec<u8>>::get_be(&mut &[0x41, 0][..]),
+            Err(Error::InvalidData(_))
+        );
+    }
+}
@@ -1,24 +1,35 @@
 //! Adapters to allow types to be converted to a big endian binary format
 //! for use with ROTMG
+//!
+//! Implementations for structs can be generated with `#[derive(Adapter)]`,
+//! which encodes each field in order. See the documentation of the derive
+//! macro for the attributes that can be used to customize the encoding.
+//!
+//! Errors raised while decoding nested values are wrapped in
+//! `Error::Located`, which records the path to the value which could not be
+//! decoded (e.g. `NewTick.statuses[17].stats[3].value`) and its position in
+//! the buffer. Derived adapters, sequences, arrays and tuples build up the path
+//! automatically, while `Option` passes the path of its value through.
 
 mod complex;
+mod compressed;
 mod primitives;
 mod rle;
 
-pub use self::rle::RLE;
+pub use self::compressed::{CompressedInt, CompressedRLE};
+pub use self::rle::{Sequence, RLE};
+pub use bytes::{Buf, BufMut};
+pub use rotmg_packets_derive::Adapter;
 
-use bytes::{Buf, BufMut};
-use failure_derive::Fail;
+use crate::packets::DecodeOptions;
+use crate::prelude::*;
+use core::fmt::{Display, Formatter, Result as FmtResult};
 
 /// An error occurring when converting a type to or from big endian
-#[derive(Debug, Fail)]
+#[derive(Debug)]
 pub enum Error {
     /// The type could not be deserialized since there were not enough bytes
     /// remaining in the buffer.
-    #[fail(
-        display = "Not enough bytes remaining in buffer: need {} bytes, {} bytes remaining",
-        needed, remaining
-    )]
     InsufficientBytes {
         /// The number of bytes remaining in the buffer
         remaining: usize,
@@ -27,35 +38,270 @@ pub enum Error {
     },
 
     /// The data was invalid, as described by the given message
-    #[fail(display = "Invalid data: {}", _0)]
     InvalidData(String),
 
-    /// A different type of error
-    #[fail(display = "Unexpected error: {}", _0)]
-    Other(failure::Error),
+    /// A different type of error, as described by the given message
+    Other(String),
+
+    /// An error decoding a value nested inside another value, such as a field
+    /// of a struct or an item of a list
+    Located {
+        /// The path to the value, e.g. `NewTick.statuses[17].stats[3].value`
+        path: String,
+        /// The position in the buffer at which the error occurred
+        position: Position,
+        /// The error which occurred while decoding the value
+        error: Box<Error>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Error::InsufficientBytes { remaining, needed } => write!(
+                f,
+                "Not enough bytes remaining in buffer: need {} bytes, {} bytes remaining",
+                needed, remaining
+            ),
+            Error::InvalidData(message) => write!(f, "Invalid data: {}", message),
+            Error::Other(message) => write!(f, "Unexpected error: {}", message),
+            Error::Located {
+                path,
+                position,
+                error,
+            } => write!(f, "Error decoding {} {}: {}", path, position, error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Located { error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+/// The position in a buffer at which an error occurred
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Position {
+    /// The number of bytes remaining in the buffer, used when the start of
+    /// the buffer is unknown
+    Remaining(usize),
+    /// The offset from the start of the buffer
+    Offset(usize),
 }
 
-impl From<failure::Error> for Error {
-    fn from(e: failure::Error) -> Self {
-        Error::Other(e)
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Position::Remaining(remaining) => write!(f, "with {} bytes remaining", remaining),
+            Position::Offset(offset) => write!(f, "at offset {}", offset),
+        }
+    }
+}
+
+impl Error {
+    /// Add the name of a field (or of the outermost type) to the start of the
+    /// path of this error. If the error doesn't have a location yet, the
+    /// number of bytes remaining in the buffer is used as its position.
+    pub fn in_field(self, name: &str, remaining: usize) -> Self {
+        self.within(name.to_owned(), remaining)
+    }
+
+    /// Add the index of a list item to the start of the path of this error,
+    /// as with `in_field`
+    pub fn at_index(self, index: usize, remaining: usize) -> Self {
+        self.within(format!("[{}]", index), remaining)
+    }
+
+    /// Add a segment to the start of the path of this error
+    fn within(self, mut segment: String, remaining: usize) -> Self {
+        match self {
+            Error::Located {
+                path,
+                position,
+                error,
+            } => {
+                if !path.starts_with('[') {
+                    segment.push('.');
+                }
+                segment.push_str(&path);
+
+                Error::Located {
+                    path: segment,
+                    position,
+                    error,
+                }
+            }
+            error => Error::Located {
+                path: segment,
+                position: Position::Remaining(remaining),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Convert the position of this error to an offset, given the total
+    /// length of the buffer being decoded
+    pub fn with_offset(self, len: usize) -> Self {
+        match self {
+            Error::Located {
+                path,
+                position: Position::Remaining(remaining),
+                error,
+            } => Error::Located {
+                path,
+                position: Position::Offset(len.saturating_sub(remaining)),
+                error,
+            },
+            error => error,
+        }
+    }
+
+    /// Get the path to the value which could not be decoded, if known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::Located { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the underlying error, without any location
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error.root(),
+            error => error,
+        }
     }
 }
 
 /// The result of serializing or deserializing a type
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
-/// An adapter for converting a type to a ROTMG-compatible binary format
+/// An adapter for converting a type to a ROTMG-compatible binary format.
+///
+/// The methods are generic over the buffer type, so that decoding and encoding
+/// are monomorphized for the concrete buffer (usually a `&[u8]` or a
+/// `Vec<u8>`) rather than making a virtual call for every read. Trait objects
+/// (`dyn Buf` and `dyn BufMut`) can still be passed where the buffer type isn't
+/// known.
 pub trait Adapter {
     /// Deserialize an instance from the given buffer. The amount of data
     /// remaining in the buffer should be checked and
     /// [`Error::InsufficientBytes`] should be returned when appropriate.
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self>
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self>
     where
         Self: Sized;
 
+    /// Deserialize an instance like `get_be`, using the given options.
+    /// Adapters for types containing other values should pass the options on
+    /// to them, which derived adapters do automatically. By default, the
+    /// options are ignored.
+    fn get_be_with<B: Buf + ?Sized>(bytes: &mut B, options: &DecodeOptions) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let _ = options;
+        Self::get_be(bytes)
+    }
+
+    /// Deserialize exactly `count` instances as the items of a sequence,
+    /// passing the given options on to each of them. By default, each item is
+    /// decoded in turn, but adapters which can decode many items at once
+    /// (such as bytes) override this.
+    fn get_vec_with<B: Buf + ?Sized>(
+        bytes: &mut B,
+        count: usize,
+        options: &DecodeOptions,
+    ) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        // don't trust the count when allocating, it may be garbage
+        let mut items = Vec::with_capacity(count.min(bytes.remaining()));
+
+        for i in 0..count {
+            let item =
+                Self::get_be_with(bytes, options).map_err(|e| e.at_index(i, bytes.remaining()))?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// Serialize the items of a sequence into the given buffer, one after
+    /// another. Adapters overriding `get_vec_with` may also override this.
+    fn put_slice_be<B: BufMut + ?Sized>(items: &[Self], buffer: &mut B) -> Result<()>
+    where
+        Self: Sized,
+    {
+        items.iter().try_for_each(|i| i.put_be(buffer))
+    }
+
     /// Serialize an instance into the given buffer. It may be assumed that the
     /// buffer will be large enough to store the entire encoded sequence, so no
     /// size checks are necessary. It is recommended that a growable buffer is
     /// used to ensure this is the case when directly calling this method.
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()>;
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()>;
+
+    /// Get the exact number of bytes `put_be` would write for this instance,
+    /// so buffers can be allocated up front. When `put_be` would fail, the
+    /// returned length is unspecified.
+    fn encoded_len(&self) -> usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Adapter)]
+    struct Derived {
+        count: u8,
+        #[adapter(count = count)]
+        items: Vec<u16>,
+        #[adapter(len = u32)]
+        name: String,
+        fixed: [u8; 3],
+        #[adapter(skip, default = 7)]
+        skipped: u32,
+        #[adapter(trailing)]
+        extra: u8,
+    }
+
+    #[test]
+    fn test_derived_adapter() {
+        let value = Derived {
+            count: 2,
+            items: vec![1, 2],
+            name: "abc".to_owned(),
+            fixed: [4, 5, 6],
+            skipped: 7,
+            extra: 8,
+        };
+
+        let mut buf = vec![];
+        value.put_be(&mut buf).expect("encoding error");
+        assert_eq!(value.encoded_len(), buf.len());
+        assert_eq!(
+            buf,
+            vec![2, 0, 1, 0, 2, 0, 0, 0, 3, b'a', b'b', b'c', 4, 5, 6, 8]
+        );
+
+        let output = Derived::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(output, value);
+
+        // the trailing field should be defaulted when no bytes remain
+        let output = Derived::get_be(&mut &buf[..buf.len() - 1]).expect("decoding error");
+        assert_eq!(output, Derived { extra: 0, ..value });
+
+        // counted sequences must match their count to be encoded
+        let value = Derived { count: 3, ..output };
+        assert!(matches!(
+            value.put_be(&mut vec![]),
+            Err(Error::InvalidData(_))
+        ));
+    }
 }
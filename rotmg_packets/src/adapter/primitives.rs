@@ -1,47 +1,70 @@
 //! Implementations of `Adapter` for standard primitive types
 
 use super::{Adapter, Error, Result};
+use crate::packets::DecodeOptions;
+use crate::prelude::*;
 use bytes::{Buf, BufMut};
-use std::mem::size_of;
+use core::convert::TryInto;
+use core::mem::size_of;
 
 // define macros for integer and floating point types
 macro_rules! int_adapter {
     ($($type:ty),* $(,)?) => {
         $(
-            impl Adapter for $type {
-                fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
-                    if bytes.remaining() < size_of::<Self>() {
-                        Err(Error::InsufficientBytes {
-                            remaining: bytes.remaining(),
-                            needed: size_of::<Self>(),
-                        })
-                    } else {
-                        let mut raw = [0u8; size_of::<Self>()];
-                        bytes.copy_to_slice(&mut raw[..]);
-                        Ok(Self::from_be_bytes(raw))
-                    }
+            int_adapter!($type {});
+        )*
+    };
+
+    // an adapter with extra methods
+    ($type:ty { $($extra:item)* }) => {
+        impl Adapter for $type {
+            fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+                if bytes.remaining() < size_of::<Self>() {
+                    Err(Error::InsufficientBytes {
+                        remaining: bytes.remaining(),
+                        needed: size_of::<Self>(),
+                    })
+                } else if let Some(raw) = bytes.chunk().get(..size_of::<Self>()) {
+                    // fast path when the value is contiguous in the buffer
+                    let value = Self::from_be_bytes(raw.try_into().unwrap());
+                    bytes.advance(size_of::<Self>());
+                    Ok(value)
+                } else {
+                    let mut raw = [0u8; size_of::<Self>()];
+                    bytes.copy_to_slice(&mut raw[..]);
+                    Ok(Self::from_be_bytes(raw))
                 }
+            }
 
-                fn put_be(&self, bytes: &mut dyn BufMut) -> Result<()> {
-                    bytes.put_slice(&self.to_be_bytes());
-                    Ok(())
-                }
+            fn put_be<B: BufMut + ?Sized>(&self, bytes: &mut B) -> Result<()> {
+                bytes.put_slice(&self.to_be_bytes());
+                Ok(())
             }
-        )*
-    }
+
+            fn encoded_len(&self) -> usize {
+                size_of::<Self>()
+            }
+
+            $($extra)*
+        }
+    };
 }
 
 macro_rules! float_adapter {
     ($($type:ty),* $(,)?) => {
         $(
             impl Adapter for $type {
-                fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
+                fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
                     Adapter::get_be(bytes).map(Self::from_bits)
                 }
 
-                fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+                fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
                     self.to_bits().put_be(buffer)
                 }
+
+                fn encoded_len(&self) -> usize {
+                    size_of::<Self>()
+                }
             }
         )*
     }
@@ -49,30 +72,60 @@ macro_rules! float_adapter {
 
 // use the macros
 int_adapter! {
-    u8, u16, u32, u64, u128,
+    u16, u32, u64, u128,
     i8, i16, i32, i64, i128,
 }
 
+// lists of bytes are copied in one go, rather than decoding each byte
+int_adapter!(u8 {
+    fn get_vec_with<B: Buf + ?Sized>(
+        bytes: &mut B,
+        count: usize,
+        _options: &DecodeOptions,
+    ) -> Result<Vec<u8>> {
+        if bytes.remaining() < count {
+            return Err(Error::InsufficientBytes {
+                remaining: bytes.remaining(),
+                needed: count,
+            });
+        }
+
+        let mut items = vec![0u8; count];
+        bytes.copy_to_slice(&mut items[..]);
+        Ok(items)
+    }
+
+    fn put_slice_be<B: BufMut + ?Sized>(items: &[u8], buffer: &mut B) -> Result<()> {
+        buffer.put_slice(items);
+        Ok(())
+    }
+});
+
 float_adapter! { f32, f64 }
 
 // manually define an adapter for booleans
 impl Adapter for bool {
-    fn get_be(bytes: &mut dyn Buf) -> Result<bool>
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<bool>
     where
         Self: Sized,
     {
         u8::get_be(bytes).map(|b| b != 0)
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
         (*self as u8).put_be(buffer)
     }
+
+    fn encoded_len(&self) -> usize {
+        1
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::adapter::Adapter;
-    use bytes::{Buf, IntoBuf};
+    use crate::prelude::*;
+    use bytes::Buf;
 
     #[test]
     fn test_primitive_adapters() {
@@ -97,27 +150,61 @@ mod tests {
 
         true.put_be(&mut buffer).unwrap();
 
+        assert_eq!(
+            buffer.len(),
+            1 + 2 + 4 + 8 + 16 + 1 + 2 + 4 + 8 + 16 + 4 + 8 + 1
+        );
+        assert_eq!(0.5f32.encoded_len(), 4);
+
         // read some data
 
-        let mut reader = buffer.into_buf();
+        let mut reader = &buffer[..];
 
-        assert_eq!(123u8, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(123u16, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(123u32, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(123u64, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(123u128, Adapter::get_be(&mut reader).unwrap());
+        assert_eq!(123u8, u8::get_be(&mut reader).unwrap());
+        assert_eq!(123u16, u16::get_be(&mut reader).unwrap());
+        assert_eq!(123u32, u32::get_be(&mut reader).unwrap());
+        assert_eq!(123u64, u64::get_be(&mut reader).unwrap());
+        assert_eq!(123u128, u128::get_be(&mut reader).unwrap());
 
-        assert_eq!(-123i8, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(-123i16, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(-123i32, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(-123i64, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(-123i128, Adapter::get_be(&mut reader).unwrap());
+        assert_eq!(-123i8, i8::get_be(&mut reader).unwrap());
+        assert_eq!(-123i16, i16::get_be(&mut reader).unwrap());
+        assert_eq!(-123i32, i32::get_be(&mut reader).unwrap());
+        assert_eq!(-123i64, i64::get_be(&mut reader).unwrap());
+        assert_eq!(-123i128, i128::get_be(&mut reader).unwrap());
 
-        assert_eq!(3.14f32, Adapter::get_be(&mut reader).unwrap());
-        assert_eq!(3.14f64, Adapter::get_be(&mut reader).unwrap());
+        assert_eq!(3.14f32, f32::get_be(&mut reader).unwrap());
+        assert_eq!(3.14f64, f64::get_be(&mut reader).unwrap());
 
-        assert_eq!(true, Adapter::get_be(&mut reader).unwrap());
+        assert_eq!(true, bool::get_be(&mut reader).unwrap());
 
         assert_eq!(reader.remaining(), 0);
     }
+
+    #[test]
+    fn test_split_buffers() {
+        // values split across the chunks of a buffer are copied out
+        let mut reader = (&[0x12, 0x34][..]).chain(&[0x56, 0x78, 0x9a][..]);
+        assert_eq!(0x1234_5678u32, u32::get_be(&mut reader).unwrap());
+        assert_eq!(0x9au8, u8::get_be(&mut reader).unwrap());
+
+        // trait objects can still be used as buffers
+        let reader: &mut dyn Buf = &mut &[0, 1][..];
+        assert_eq!(1u16, u16::get_be(reader).unwrap());
+    }
+
+    #[test]
+    fn test_byte_lists() {
+        use crate::adapter::Sequence;
+
+        // lists of bytes are copied across chunks in one go
+        let mut reader = (&[1, 2][..]).chain(&[3, 4, 5][..]);
+        let bytes = Vec::<u8>::get_items(&mut reader, 4).unwrap();
+        assert_eq!(bytes, vec![1, 2, 3, 4]);
+        assert_eq!(reader.remaining(), 1);
+        assert!(Vec::<u8>::get_items(&mut reader, 2).is_err());
+
+        let mut buffer = vec![];
+        bytes.put_items(&mut buffer).unwrap();
+        assert_eq!(buffer, bytes);
+    }
 }
@@ -2,13 +2,19 @@
 //! values
 
 use super::{Adapter, Error, Result};
+use crate::packets::DecodeOptions;
+use crate::prelude::*;
 use bytes::{Buf, BufMut};
+use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::result::Result as StdResult;
+#[cfg(feature = "quickcheck")]
+use num::Bounded;
 use num::{FromPrimitive, ToPrimitive};
+#[cfg(feature = "quickcheck")]
+use quickcheck::{Arbitrary, Gen};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use std::marker::PhantomData;
-use std::ops::Deref;
-use std::result::Result as StdResult;
 
 /// A wrapper around a value (of type `T`) which can be serialized or
 /// deserialized by prefixing the data with an integer representing the length
@@ -20,7 +26,6 @@ use std::result::Result as StdResult;
 /// Encode a vector of bytes, prefixed with an unsigned 16-bit length:
 /// ```
 /// # use crate::rotmg_packets::adapter::{Adapter, RLE};
-/// # use std::io::Cursor;
 /// // wrap the bytes
 /// let bytes: RLE<Vec<u8>> = RLE::new(vec![1, 2, 3]);
 ///
@@ -51,22 +56,72 @@ impl<T, S> RLE<T, S> {
     }
 }
 
-impl<T, S> Adapter for RLE<Vec<T>, S>
-where
-    T: Adapter,
-    S: Adapter + ToPrimitive + FromPrimitive + Display,
-{
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
+/// A variable length sequence of items which may be encoded with or without a
+/// length prefix. Implementations are provided for `Vec<T>` and `String`, and
+/// are used by `RLE` and `#[derive(Adapter)]`.
+pub trait Sequence: Sized {
+    /// The number of items in this sequence
+    fn count(&self) -> usize;
+
+    /// Decode a sequence of exactly `count` items from the given buffer
+    fn get_items<B: Buf + ?Sized>(bytes: &mut B, count: usize) -> Result<Self>;
+
+    /// Decode a sequence of exactly `count` items like `get_items`, passing
+    /// the given options on to the items. By default, the options are
+    /// ignored.
+    fn get_items_with<B: Buf + ?Sized>(
+        bytes: &mut B,
+        count: usize,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let _ = options;
+        Self::get_items(bytes, count)
+    }
+
+    /// Encode every item in this sequence into the given buffer, without a
+    /// length prefix
+    fn put_items<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()>;
+
+    /// Get the number of bytes `put_items` would write for this sequence
+    fn items_len(&self) -> usize;
+
+    /// Encode every item in this sequence into the given buffer, without a
+    /// length prefix, checking that there are exactly `count` items so that
+    /// the sequence can be decoded again
+    fn put_counted<B: BufMut + ?Sized>(&self, count: usize, buffer: &mut B) -> Result<()> {
+        if self.count() == count {
+            self.put_items(buffer)
+        } else {
+            Err(Error::InvalidData(format!(
+                "expected {} items, found {}",
+                count,
+                self.count()
+            )))
+        }
+    }
+
+    /// Decode a sequence prefixed by its length, of type `S`
+    fn get_prefixed<S, B>(bytes: &mut B) -> Result<Self>
+    where
+        S: Adapter + ToPrimitive + Display,
+        B: Buf + ?Sized,
+    {
+        Self::get_prefixed_with::<S, B>(bytes, &DecodeOptions::default())
+    }
+
+    /// Decode a sequence prefixed by its length like `get_prefixed`, passing
+    /// the given options on to the items
+    fn get_prefixed_with<S, B>(bytes: &mut B, options: &DecodeOptions) -> Result<Self>
+    where
+        S: Adapter + ToPrimitive + Display,
+        B: Buf + ?Sized,
+    {
         // decode length
         let len = S::get_be(bytes)?;
 
         // attempt to convert length to usize
         if let Some(len) = len.to_usize() {
-            // decode remaining items and convert to vec
-            (0..len)
-                .map(|_| T::get_be(bytes))
-                .collect::<Result<Vec<T>>>()
-                .map(Self::new)
+            Self::get_items_with(bytes, len, options)
         } else {
             Err(Error::InvalidData(format!(
                 "cannot cast length to usize: {}",
@@ -75,45 +130,107 @@ where
         }
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+    /// Encode this sequence prefixed by its length, of type `S`
+    fn put_prefixed<S, B>(&self, buffer: &mut B) -> Result<()>
+    where
+        S: Adapter + FromPrimitive,
+        B: BufMut + ?Sized,
+    {
         // attempt to convert length from a usize
-        if let Some(len) = S::from_usize(self.inner.len()) {
-            // encode length and then each element
+        if let Some(len) = S::from_usize(self.count()) {
+            // encode length and then the items
             len.put_be(buffer)?;
-            self.inner.iter().try_for_each(|i| i.put_be(buffer))
+            self.put_items(buffer)
         } else {
             Err(Error::InvalidData(format!(
                 "cannot cast length from usize: {}",
-                self.inner.len()
+                self.count()
             )))
         }
     }
+
+    /// Get the number of bytes `put_prefixed` would write for this sequence
+    fn prefixed_len<S>(&self) -> usize
+    where
+        S: Adapter + FromPrimitive,
+    {
+        S::from_usize(self.count()).map_or(0, |len| len.encoded_len()) + self.items_len()
+    }
+}
+
+impl<T: Adapter> Sequence for Vec<T> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn get_items<B: Buf + ?Sized>(bytes: &mut B, count: usize) -> Result<Self> {
+        Self::get_items_with(bytes, count, &DecodeOptions::default())
+    }
+
+    fn get_items_with<B: Buf + ?Sized>(
+        bytes: &mut B,
+        count: usize,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        T::get_vec_with(bytes, count, options)
+    }
+
+    fn put_items<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+        T::put_slice_be(self, buffer)
+    }
+
+    fn items_len(&self) -> usize {
+        self.iter().map(T::encoded_len).sum()
+    }
 }
 
-impl<S> Adapter for RLE<String, S>
+impl Sequence for String {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn get_items<B: Buf + ?Sized>(bytes: &mut B, count: usize) -> Result<Self> {
+        if bytes.remaining() < count {
+            return Err(Error::InsufficientBytes {
+                remaining: bytes.remaining(),
+                needed: count,
+            });
+        }
+
+        let mut raw = vec![0u8; count];
+        bytes.copy_to_slice(&mut raw[..]);
+        String::from_utf8(raw).map_err(|e| Error::Other(e.to_string()))
+    }
+
+    fn put_items<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+        buffer.put_slice(self.as_bytes());
+        Ok(())
+    }
+
+    fn items_len(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T, S> Adapter for RLE<T, S>
 where
+    T: Sequence,
     S: Adapter + ToPrimitive + FromPrimitive + Display,
 {
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
-        // use the RLE<Vec<u8>> adapter to simplify this
-        RLE::<Vec<u8>, S>::get_be(bytes)
-            .and_then(|bytes| String::from_utf8(bytes.unwrap()).map_err(|e| Error::Other(e.into())))
-            .map(Self::new)
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+        T::get_prefixed::<S, _>(bytes).map(Self::new)
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
-        // attempt to convert length from a usize
-        if let Some(len) = S::from_usize(self.inner.len()) {
-            // encode length and then each byte
-            len.put_be(buffer)?;
-            buffer.put_slice(self.inner.as_bytes());
-            Ok(())
-        } else {
-            Err(Error::InvalidData(format!(
-                "cannot cast length from usize: {}",
-                self.inner.len()
-            )))
-        }
+    fn get_be_with<B: Buf + ?Sized>(bytes: &mut B, options: &DecodeOptions) -> Result<Self> {
+        T::get_prefixed_with::<S, _>(bytes, options).map(Self::new)
+    }
+
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+        self.inner.put_prefixed::<S, _>(buffer)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.inner.prefixed_len::<S>()
     }
 }
 
@@ -155,12 +272,58 @@ impl<T: Clone, S> Clone for RLE<T, S> {
     }
 }
 
+/// The maximum number of items which can be encoded with a length prefix of
+/// type `S`
+#[cfg(feature = "quickcheck")]
+fn max_len<S: Bounded + ToPrimitive>() -> usize {
+    S::max_value().to_usize().unwrap_or(usize::MAX)
+}
+
+/// Generates vectors short enough for their length to be encoded as `S`
+#[cfg(feature = "quickcheck")]
+impl<T, S> Arbitrary for RLE<Vec<T>, S>
+where
+    T: Arbitrary,
+    S: Bounded + ToPrimitive + Send + 'static,
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut items = Vec::<T>::arbitrary(g);
+        items.truncate(max_len::<S>());
+        Self::new(items)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.inner.shrink().map(Self::new))
+    }
+}
+
+/// Generates strings short enough for their length in bytes to be encoded as
+/// `S`
+#[cfg(feature = "quickcheck")]
+impl<S> Arbitrary for RLE<String, S>
+where
+    S: Bounded + ToPrimitive + Send + 'static,
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut string = String::arbitrary(g);
+
+        while string.len() > max_len::<S>() {
+            string.pop();
+        }
+
+        Self::new(string)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.inner.shrink().map(Self::new))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_matches::assert_matches;
-    use std::io::Cursor;
-    use std::mem::size_of;
+    use core::mem::size_of;
 
     #[test]
     fn check_rle_size() {
@@ -174,8 +337,9 @@ mod tests {
             .put_be(&mut buf)
             .expect("encoding error");
         assert_eq!(buf, vec![0, 5, 1, 2, 3, 4, 5]);
+        assert_eq!(RLE::<Vec<u32>, u8>::new(vec![1, 2]).encoded_len(), 9);
 
-        let output = RLE::<Vec<u8>>::get_be(&mut Cursor::new(&buf)).expect("decoding error");
+        let output = RLE::<Vec<u8>>::get_be(&mut &buf[..]).expect("decoding error");
         assert_eq!(output.unwrap(), vec![1, 2, 3, 4, 5]);
 
         let large = (0..300).collect::<Vec<u16>>();
@@ -199,8 +363,9 @@ mod tests {
         };
 
         assert_eq!(buf, expected_encoded);
+        assert_eq!(RLE::<String, u32>::new("abc".to_owned()).encoded_len(), 7);
 
-        let output = RLE::<String>::get_be(&mut Cursor::new(&buf)).expect("decoding error");
+        let output = RLE::<String>::get_be(&mut &buf[..]).expect("decoding error");
         assert_eq!(output.unwrap(), "hello world");
 
         let large = "abc".repeat(100);
@@ -0,0 +1,570 @@
+//! Dissection of packets into trees of fields annotated with their location in
+//! the binary contents, for inspecting and reverse engineering packets.
+//!
+//! Dissection is driven by the schema of the packet type (see the `schema`
+//! module). Unlike normal decoding, it never gives up on the whole packet:
+//! when a field can't be decoded, every field decoded before it is still
+//! returned along with the location of the error.
+//!
+//! # Examples
+//!
+//! ```
+//! # use rotmg_packets::dissect::{dissect, Value};
+//! # use rotmg_packets::packets::PacketType;
+//! // a Failure packet with a truncated description
+//! let dissection = dissect(PacketType::Failure, &[0, 0, 0, 1, 0, 5, b'o', b'o']);
+//!
+//! assert_eq!(dissection.fields[0].value, Value::Unsigned(1));
+//! assert_eq!(dissection.fields[1].offset, 4);
+//!
+//! let error = dissection.error.unwrap();
+//! assert_eq!(error.path, "error_description");
+//! assert_eq!(error.offset, 6);
+//! ```
+
+use crate::adapter::{Adapter, CompressedInt, Error as AdapterError, Result, Sequence};
+use crate::packets::PacketType;
+use crate::prelude::*;
+use crate::schema::{Length, Presence, StructSchema, WireType};
+use bytes::Buf;
+use core::convert::TryFrom;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::result::Result as StdResult;
+
+/// A field of a dissected packet
+#[derive(Debug, PartialEq, Clone)]
+pub struct Field {
+    /// The name of the field. Items of tuples are named by their index, and
+    /// items of lists by their index in brackets, e.g. `[0]`.
+    pub name: String,
+    /// The offset of the field from the start of the packet contents
+    pub offset: usize,
+    /// The number of bytes taken up by the field
+    pub len: usize,
+    /// The decoded value of the field
+    pub value: Value,
+}
+
+/// The decoded value of a field
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    /// A boolean
+    Bool(bool),
+    /// An unsigned integer
+    Unsigned(u128),
+    /// A signed integer
+    Signed(i128),
+    /// A floating point number
+    Float(f64),
+    /// A string
+    String(String),
+    /// A list of bytes
+    Bytes(Vec<u8>),
+    /// A value with a custom format, formatted with `Debug`
+    Custom(String),
+    /// A struct, tuple or list made up of the given fields. The length prefix
+    /// of a list is included as a field named `len`.
+    Compound(Vec<Field>),
+    /// An optional or trailing value which is not present
+    Absent,
+    /// A value which could not be decoded, along with the error message
+    Invalid(String),
+}
+
+/// The result of dissecting a packet
+#[derive(Debug)]
+pub struct Dissection {
+    /// The fields of the packet. If an error occurred, only the fields up to
+    /// and including the one which could not be decoded are present.
+    pub fields: Vec<Field>,
+    /// The error which stopped dissection, if any
+    pub error: Option<DissectError>,
+    /// The bytes remaining after every field was decoded
+    pub trailing: Vec<u8>,
+}
+
+/// An error decoding a field of a packet
+#[derive(Debug)]
+pub struct DissectError {
+    /// The path of the field which could not be decoded, e.g. `records[2].x`
+    pub path: String,
+    /// The offset of the data which could not be decoded
+    pub offset: usize,
+    /// The error raised while decoding
+    pub error: AdapterError,
+}
+
+impl Display for DissectError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "Error decoding {} at offset {}: {}",
+            self.path, self.offset, self.error
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DissectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Dissect the binary contents of a packet of the given type
+pub fn dissect(typ: PacketType, contents: &[u8]) -> Dissection {
+    dissect_struct(typ.schema(), contents)
+}
+
+/// Dissect the binary contents of a struct with the given schema
+pub fn dissect_struct(schema: &StructSchema, contents: &[u8]) -> Dissection {
+    let mut dissector = Dissector {
+        contents,
+        remaining: contents,
+        path: vec![],
+    };
+
+    let mut fields = vec![];
+    let error = dissector.fields(schema, &mut fields).err();
+    let trailing = match error {
+        Some(_) => vec![],
+        None => contents[dissector.offset()..].to_vec(),
+    };
+
+    Dissection {
+        fields,
+        error,
+        trailing,
+    }
+}
+
+/// A partially decoded value, along with the error which stopped decoding
+type Partial = (Value, DissectError);
+
+/// The state of a dissection in progress
+struct Dissector<'a> {
+    contents: &'a [u8],
+    remaining: &'a [u8],
+    path: Vec<String>,
+}
+
+impl<'a> Dissector<'a> {
+    /// Get the current offset from the start of the contents
+    fn offset(&self) -> usize {
+        self.contents.len() - self.remaining.len()
+    }
+
+    /// Create an error for the value at the given offset
+    fn fail(&self, offset: usize, error: AdapterError) -> Partial {
+        let mut path = String::new();
+        for name in &self.path {
+            if !path.is_empty() && !name.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(name);
+        }
+
+        let value = Value::Invalid(error.to_string());
+        (
+            value,
+            DissectError {
+                path,
+                offset,
+                error,
+            },
+        )
+    }
+
+    /// Decode a value using the given function
+    fn read_with<T>(
+        &mut self,
+        read: impl FnOnce(&mut dyn Buf) -> Result<T>,
+    ) -> StdResult<T, Partial> {
+        let offset = self.offset();
+        read(&mut self.remaining).map_err(|e| self.fail(offset, e))
+    }
+
+    /// Decode a value using its `Adapter` implementation
+    fn read<T: Adapter>(&mut self) -> StdResult<T, Partial> {
+        self.read_with(|buf| T::get_be(buf))
+    }
+
+    /// Decode the fields of a struct, adding them to `fields`
+    fn fields(
+        &mut self,
+        schema: &StructSchema,
+        fields: &mut Vec<Field>,
+    ) -> StdResult<(), DissectError> {
+        for field in schema.fields {
+            match field.presence {
+                Presence::Skipped => {}
+                Presence::Trailing if self.remaining.is_empty() => fields.push(Field {
+                    name: field.name.to_owned(),
+                    offset: self.offset(),
+                    len: 0,
+                    value: Value::Absent,
+                }),
+                _ => self.field(field.name.to_owned(), &field.wire, fields)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode a single field, adding it to `fields`. The fields decoded so far
+    /// may be referred to by `count` expressions.
+    fn field(
+        &mut self,
+        name: String,
+        wire: &WireType,
+        fields: &mut Vec<Field>,
+    ) -> StdResult<(), DissectError> {
+        let offset = self.offset();
+
+        self.path.push(name.clone());
+        let result = self.value(wire, fields);
+        self.path.pop();
+
+        let (value, error) = match result {
+            Ok(value) => (value, None),
+            Err((value, error)) => (value, Some(error)),
+        };
+
+        fields.push(Field {
+            name,
+            offset,
+            len: self.offset() - offset,
+            value,
+        });
+
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Decode a compound value, with fields added by the given function
+    fn compound(
+        &mut self,
+        add_fields: impl FnOnce(&mut Self, &mut Vec<Field>) -> StdResult<(), DissectError>,
+    ) -> StdResult<Value, Partial> {
+        let mut fields = vec![];
+
+        match add_fields(self, &mut fields) {
+            Ok(()) => Ok(Value::Compound(fields)),
+            Err(error) => Err((Value::Compound(fields), error)),
+        }
+    }
+
+    /// Decode the length of a sequence which isn't prefixed
+    fn count(&self, length: Length, siblings: &[Field]) -> StdResult<usize, Partial> {
+        let count = match length {
+            Length::Fixed(count) => Ok(count),
+            Length::Counted(count) => (count.eval)(&|name| field_value(siblings, name))
+                .ok_or_else(|| format!("invalid count: {}", count.expr)),
+            Length::Prefixed(_) => unreachable!("prefixed lengths must be decoded"),
+        };
+
+        count.map_err(|e| self.fail(self.offset(), AdapterError::InvalidData(e)))
+    }
+
+    /// Decode the length of a sequence, reading the prefix if it has one
+    fn length(&mut self, length: Length, siblings: &[Field]) -> StdResult<usize, Partial> {
+        match length {
+            Length::Prefixed(prefix) => {
+                let offset = self.offset();
+                let value = self.value(prefix, &[])?;
+                to_count(&value).ok_or_else(|| {
+                    self.fail(offset, AdapterError::InvalidData(invalid_len(&value)))
+                })
+            }
+            length => self.count(length, siblings),
+        }
+    }
+
+    /// Decode a value of the given type
+    fn value(&mut self, wire: &WireType, siblings: &[Field]) -> StdResult<Value, Partial> {
+        match *wire {
+            WireType::Bool => self.read().map(Value::Bool),
+            WireType::U8 => self.read::<u8>().map(|v| Value::Unsigned(v.into())),
+            WireType::U16 => self.read::<u16>().map(|v| Value::Unsigned(v.into())),
+            WireType::U32 => self.read::<u32>().map(|v| Value::Unsigned(v.into())),
+            WireType::U64 => self.read::<u64>().map(|v| Value::Unsigned(v.into())),
+            WireType::U128 => self.read::<u128>().map(Value::Unsigned),
+            WireType::I8 => self.read::<i8>().map(|v| Value::Signed(v.into())),
+            WireType::I16 => self.read::<i16>().map(|v| Value::Signed(v.into())),
+            WireType::I32 => self.read::<i32>().map(|v| Value::Signed(v.into())),
+            WireType::I64 => self.read::<i64>().map(|v| Value::Signed(v.into())),
+            WireType::I128 => self.read::<i128>().map(Value::Signed),
+            WireType::F32 => self.read::<f32>().map(|v| Value::Float(v.into())),
+            WireType::F64 => self.read::<f64>().map(Value::Float),
+            WireType::CompressedInt => self
+                .read::<CompressedInt>()
+                .map(|v| Value::Signed(v.0.into())),
+            WireType::String(length) => {
+                let count = self.length(length, siblings)?;
+                self.read_with(|buf| String::get_items(buf, count))
+                    .map(Value::String)
+            }
+            WireType::List(length, WireType::U8) => {
+                let count = self.length(length, siblings)?;
+                self.read_with(|buf| Vec::<u8>::get_items(buf, count))
+                    .map(Value::Bytes)
+            }
+            WireType::List(length, item) => self.compound(|d, fields| {
+                let count = match length {
+                    Length::Prefixed(prefix) => {
+                        d.field("len".to_owned(), prefix, fields)?;
+                        let value = &fields[0].value;
+                        to_count(value).ok_or_else(|| {
+                            let error = AdapterError::InvalidData(invalid_len(value));
+                            d.fail(fields[0].offset, error).1
+                        })?
+                    }
+                    length => d.count(length, siblings).map_err(|(_, e)| e)?,
+                };
+
+                for i in 0..count {
+                    d.field(format!("[{}]", i), item, fields)?;
+                }
+
+                Ok(())
+            }),
+            WireType::Tuple(items) => self.compound(|d, fields| {
+                for (i, item) in items.iter().enumerate() {
+                    d.field(i.to_string(), item, fields)?;
+                }
+
+                Ok(())
+            }),
+            WireType::Optional(inner) => {
+                if !self.remaining.is_empty() {
+                    self.value(inner, siblings)
+                } else {
+                    Ok(Value::Absent)
+                }
+            }
+            WireType::Struct(schema) => self.compound(|d, fields| d.fields(schema, fields)),
+            WireType::Custom(custom) => self.read_with(custom.decode).map(Value::Custom),
+        }
+    }
+}
+
+/// Convert a decoded length prefix to a number of items
+fn to_count(value: &Value) -> Option<usize> {
+    match *value {
+        Value::Unsigned(n) => usize::try_from(n).ok(),
+        Value::Signed(n) => usize::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+/// Describe an invalid length prefix
+fn invalid_len(value: &Value) -> String {
+    format!("invalid length: {}", value)
+}
+
+/// Get the value of the integer field with the given name, as used by
+/// `count` expressions
+fn field_value(fields: &[Field], name: &str) -> Option<i128> {
+    match fields.iter().find(|f| f.name == name)?.value {
+        Value::Unsigned(n) => i128::try_from(n).ok(),
+        Value::Signed(n) => Some(n),
+        _ => None,
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unsigned(n) => write!(f, "{}", n),
+            Value::Signed(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bytes(bytes) => write!(f, "{}", hex::encode(bytes)),
+            Value::Custom(s) => write!(f, "{}", s),
+            Value::Compound(fields) => write!(f, "({} fields)", fields.len()),
+            Value::Absent => write!(f, "(absent)"),
+            Value::Invalid(e) => write!(f, "<{}>", e),
+        }
+    }
+}
+
+/// Write fields as an indented tree, one per line, prefixed by their offset and
+/// length
+fn write_fields(f: &mut Formatter, fields: &[Field], depth: usize) -> FmtResult {
+    for field in fields {
+        write!(
+            f,
+            "{:>6} {:>6}  {:indent$}{}",
+            field.offset,
+            field.len,
+            "",
+            field.name,
+            indent = depth * 2
+        )?;
+
+        match &field.value {
+            Value::Compound(children) => {
+                writeln!(f)?;
+                write_fields(f, children, depth + 1)?;
+            }
+            value => writeln!(f, ": {}", value)?,
+        }
+    }
+
+    Ok(())
+}
+
+impl Display for Dissection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        writeln!(f, "{:>6} {:>6}  field", "offset", "len")?;
+        write_fields(f, &self.fields, 0)?;
+
+        if let Some(error) = &self.error {
+            writeln!(f, "{}", error)?;
+        }
+
+        if !self.trailing.is_empty() {
+            writeln!(
+                f,
+                "{} trailing bytes: {}",
+                self.trailing.len(),
+                hex::encode(&self.trailing)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::RLE;
+    use crate::packets::client::Move;
+    use crate::packets::data::{MoveRecord, ObjectStatusData, StatData, StatType, WorldPosData};
+    use crate::packets::server::Pic;
+    use crate::schema::Schema;
+
+    #[test]
+    fn test_dissect_move() {
+        let packet = Move {
+            tick_id: 1,
+            time: 2,
+            new_pos: WorldPosData { x: 0.5, y: 1.5 },
+            records: RLE::new(vec![
+                MoveRecord {
+                    time: 3,
+                    x: 4.0,
+                    y: 5.0,
+                },
+                MoveRecord {
+                    time: 6,
+                    x: 7.0,
+                    y: 8.0,
+                },
+            ]),
+        };
+
+        let mut buf = vec![];
+        packet.put_be(&mut buf).unwrap();
+        buf.push(0xff);
+
+        let dissection = dissect(PacketType::Move, &buf);
+        assert!(dissection.error.is_none());
+        assert_eq!(dissection.trailing, vec![0xff]);
+
+        let names = dissection
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.offset, f.len))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("tick_id", 0, 4),
+                ("time", 4, 4),
+                ("new_pos", 8, 8),
+                ("records", 16, 26)
+            ]
+        );
+
+        match &dissection.fields[3].value {
+            Value::Compound(items) => {
+                assert_eq!(items[0].value, Value::Unsigned(2));
+                assert_eq!(items[2].offset, 30);
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        // truncate the packet in the middle of the second record
+        let dissection = dissect(PacketType::Move, &buf[..35]);
+        let error = dissection.error.as_ref().expect("no error");
+        assert_eq!(error.path, "records[1].x");
+        assert_eq!(error.offset, 34);
+
+        let text = dissection.to_string();
+        assert!(text.contains("    30      4    [1]\n"), "{}", text);
+        assert!(text.contains("    30      4      time: 6\n"), "{}", text);
+        assert!(
+            text.contains("Error decoding records[1].x at offset 34"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_dissect_counted_and_custom() {
+        let pic = Pic {
+            w: 1,
+            h: 2,
+            bitmap_data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        };
+
+        let mut buf = vec![];
+        pic.put_be(&mut buf).unwrap();
+
+        let dissection = dissect(PacketType::Pic, &buf);
+        assert!(dissection.error.is_none());
+        assert_eq!(
+            dissection.fields[2].value,
+            Value::Bytes(vec![1, 2, 3, 4, 5, 6, 7, 8])
+        );
+
+        let status = ObjectStatusData {
+            object_id: 1,
+            pos: WorldPosData { x: 0.0, y: 0.0 },
+            stats: RLE::new(vec![StatData::Integer(StatType::HP_STAT, 100)]),
+        };
+
+        buf.clear();
+        status.put_be(&mut buf).unwrap();
+
+        let schema = ObjectStatusData::WIRE.as_struct().unwrap();
+        let dissection = dissect_struct(schema, &buf);
+        assert!(dissection.error.is_none());
+
+        match &dissection.fields[2].value {
+            Value::Compound(items) => {
+                assert_eq!(items[1].len, 5);
+                assert_eq!(
+                    items[1].value,
+                    Value::Custom("Integer(HP_STAT, 100)".to_owned())
+                );
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_dissect_invalid_count() {
+        // the size of the bitmap overflows, but dissection fails gracefully
+        let mut buf = vec![0xff; 8];
+        buf.extend_from_slice(&[1, 2, 3]);
+
+        let dissection = dissect(PacketType::Pic, &buf);
+        assert_eq!(dissection.fields[0].value, Value::Unsigned(u32::MAX.into()));
+        let error = dissection.error.expect("no error");
+        assert_eq!(error.path, "bitmap_data");
+        assert!(Pic::get_be(&mut &buf[..]).is_err());
+    }
+}
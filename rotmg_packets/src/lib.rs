@@ -3,10 +3,41 @@
 //! This crate does not implement actual networking, it only provides types and
 //! conversions to/from decrypted binary form. Consider the rotmg_networking
 //! crate if you need the networking code as well.
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): mappings between packet IDs and types, and
+//!   `std::error::Error` implementations for errors. Without it, the crate
+//!   only depends on `core` and `alloc`, so packets can still be decoded,
+//!   encoded, dissected, viewed and written as text in `no_std` environments.
+//! - `quickcheck`: `Arbitrary` implementations for packets and their data
 
+#![no_std]
 #![deny(missing_docs)]
 #![deny(bare_trait_objects)]
 
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+// allow the derive macros to refer to this crate by name
+extern crate self as rotmg_packets;
+
 pub mod adapter;
+pub mod dissect;
+#[cfg(feature = "std")]
 pub mod mappings;
 pub mod packets;
+pub mod schema;
+pub mod text;
+
+/// The items from `alloc` which are in the standard prelude, imported by
+/// modules which need them since this crate is `no_std`
+mod prelude {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+}
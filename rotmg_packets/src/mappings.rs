@@ -1,36 +1,127 @@
 //! Mappings to convert official game packet IDs to and from `PacketType` and
 //! store RC4 keys.
+//!
+//! # File format
+//!
+//! Mappings are stored on disk as JSON, in the following format:
+//!
+//! ```json
+//! {
+//!   "build_version": "X31.2.3",
+//!   "rc4": "6a39570cc9de4ec71d64821894c79332b197f92ba85ed281a023",
+//!   "packets": {
+//!     "Failure": 0,
+//!     "Hello": 1
+//!   }
+//! }
+//! ```
+//!
+//! - `build_version` is the version of the client the mappings were extracted
+//!   from (as in `Parameters.version`), and may be `null` if unknown
+//! - `rc4` is the RC4 key, in hex
+//! - `packets` maps the names of packet types (as in `PacketType::get_name`) to
+//!   the IDs used by the game, ordered by name
+//!
+//! This format is also used when serializing `Mappings` with serde.
 
 use crate::packets::PacketType;
+use crate::prelude::*;
 use bimap::BiHashMap;
-use failure_derive::Fail;
 use hex::FromHexError;
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::convert::TryInto;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::result::Result as StdResult;
 
 /// The length of RC4 keys in their binary representation, in bytes
 pub const RC4_LEN: usize = 26;
 
 /// A set of mappings, used to convert ROTMG packet IDs to/from `PacketType` and
 /// store initial RC4 cipher states.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct Mappings {
     mappings: BiHashMap<u8, PacketType>,
     binary_rc4: [u8; RC4_LEN],
+    build_version: Option<String>,
 }
 
 /// An error with the RC4 key when constructing mappings
-#[derive(Debug, Clone, Fail)]
+#[derive(Debug, Clone)]
 pub enum RC4KeyError {
     /// Caused by invalid hexadecimal characters in the RC4 key
-    #[fail(display = "Invalid RC4 key hex: {} for key {}", _1, _0)]
     InvalidRC4Hex(String, FromHexError),
 
     /// Caused by invalid RC4 key lengths
-    #[fail(display = "Invalid RC4 key length: {} for key {}", _1, _0)]
     InvalidRC4Len(String, usize),
 }
 
+impl Display for RC4KeyError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RC4KeyError::InvalidRC4Hex(key, e) => {
+                write!(f, "Invalid RC4 key hex: {} for key {}", e, key)
+            }
+            RC4KeyError::InvalidRC4Len(key, len) => {
+                write!(f, "Invalid RC4 key length: {} for key {}", len, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RC4KeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RC4KeyError::InvalidRC4Hex(_, e) => Some(e),
+            RC4KeyError::InvalidRC4Len(..) => None,
+        }
+    }
+}
+
+/// An error loading or saving mappings
+#[derive(Debug)]
+pub enum MappingsError {
+    /// Caused by an error reading or writing the mappings
+    Io(std::io::Error),
+
+    /// Caused by invalid or malformed mappings
+    Format(serde_json::Error),
+}
+
+impl Display for MappingsError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            MappingsError::Io(e) => write!(f, "I/O error: {}", e),
+            MappingsError::Format(e) => write!(f, "Invalid mappings: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MappingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MappingsError::Io(e) => Some(e),
+            MappingsError::Format(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for MappingsError {
+    fn from(e: std::io::Error) -> Self {
+        MappingsError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for MappingsError {
+    fn from(e: serde_json::Error) -> Self {
+        MappingsError::Format(e)
+    }
+}
+
 impl Mappings {
     /// Construct a `Mappings` instance using the given map between ROTMG packet
     /// IDs and internal packet types, and the given hexadecimal RC4 key
@@ -46,9 +137,70 @@ impl Mappings {
         Ok(Self {
             binary_rc4,
             mappings,
+            build_version: None,
         })
     }
 
+    /// Set the version of the client these mappings were extracted from
+    pub fn with_build_version(mut self, build_version: impl Into<String>) -> Self {
+        self.build_version = Some(build_version.into());
+        self
+    }
+
+    /// Get the version of the client these mappings were extracted from, if
+    /// known
+    pub fn build_version(&self) -> Option<&str> {
+        self.build_version.as_deref()
+    }
+
+    /// Read mappings in the file format from the given reader
+    pub fn from_reader(reader: impl Read) -> StdResult<Self, MappingsError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write these mappings in the file format to the given writer
+    pub fn to_writer(&self, writer: impl Write) -> StdResult<(), MappingsError> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Load mappings from the file at the given path
+    pub fn load(path: impl AsRef<Path>) -> StdResult<Self, MappingsError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Save these mappings to a file at the given path, replacing it if it
+    /// already exists
+    pub fn save(&self, path: impl AsRef<Path>) -> StdResult<(), MappingsError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    /// Compare these mappings to a newer set of mappings, finding the packet
+    /// types which were added, removed or renumbered
+    pub fn diff(&self, newer: &Mappings) -> MappingsDiff {
+        let mut diff = MappingsDiff::default();
+
+        for (&id, &typ) in self.mappings.iter() {
+            match newer.to_game(typ) {
+                None => diff.removed.push((typ, id)),
+                Some(new_id) if new_id != id => diff.renumbered.push((typ, id, new_id)),
+                Some(_) => {}
+            }
+        }
+
+        for (&id, &typ) in newer.mappings.iter() {
+            if !self.mappings.contains_right(&typ) {
+                diff.added.push((typ, id));
+            }
+        }
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.renumbered.sort();
+        diff
+    }
+
     /// Get a reference to the internal map used by this instance
     pub fn get_map(&self) -> &BiHashMap<u8, PacketType> {
         &self.mappings
@@ -80,3 +232,141 @@ impl Mappings {
         &self.binary_rc4
     }
 }
+
+/// The differences between two sets of mappings, as found by `Mappings::diff`.
+/// Each list is sorted by packet type.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MappingsDiff {
+    /// Packet types which are only present in the newer mappings, with their
+    /// IDs
+    pub added: Vec<(PacketType, u8)>,
+
+    /// Packet types which are only present in the older mappings, with their
+    /// IDs
+    pub removed: Vec<(PacketType, u8)>,
+
+    /// Packet types present in both mappings with different IDs, with their
+    /// old and new IDs respectively
+    pub renumbered: Vec<(PacketType, u8, u8)>,
+}
+
+impl MappingsDiff {
+    /// Check whether the packet IDs are identical in both mappings
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renumbered.is_empty()
+    }
+}
+
+/// The serialized representation of `Mappings`
+#[derive(Serialize, Deserialize)]
+struct MappingsFile {
+    build_version: Option<String>,
+    rc4: String,
+    packets: BTreeMap<String, u8>,
+}
+
+impl Serialize for Mappings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        let packets = self
+            .mappings
+            .iter()
+            .map(|(&id, typ)| (typ.get_name().to_owned(), id))
+            .collect();
+
+        MappingsFile {
+            build_version: self.build_version.clone(),
+            rc4: hex::encode(&self.binary_rc4[..]),
+            packets,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mappings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> StdResult<Self, D::Error> {
+        let file = MappingsFile::deserialize(deserializer)?;
+        let mut mappings = BiHashMap::new();
+
+        for (name, id) in file.packets {
+            let typ = PacketType::from_name(&name)
+                .ok_or_else(|| D::Error::custom(format!("unknown packet type: {}", name)))?;
+
+            if let Some(other) = mappings.get_by_left(&id) {
+                return Err(D::Error::custom(format!(
+                    "packet id {} is used by both {:?} and {:?}",
+                    id, other, typ
+                )));
+            }
+
+            mappings.insert(id, typ);
+        }
+
+        let mut result = Mappings::new(mappings, &file.rc4).map_err(D::Error::custom)?;
+        result.build_version = file.build_version;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mappings(packets: &[(u8, PacketType)]) -> Mappings {
+        let mut map = BiHashMap::new();
+        for &(id, typ) in packets {
+            map.insert(id, typ);
+        }
+
+        Mappings::new(map, &"ab".repeat(RC4_LEN)).unwrap()
+    }
+
+    #[test]
+    fn test_mappings_file() {
+        let original = mappings(&[(1, PacketType::Hello), (0, PacketType::Failure)])
+            .with_build_version("X31.2.3");
+
+        let mut buf = vec![];
+        original.to_writer(&mut buf).unwrap();
+
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert!(text.contains(r#""build_version": "X31.2.3""#));
+        assert!(text.contains(r#""Hello": 1"#));
+
+        let loaded = Mappings::from_reader(&buf[..]).unwrap();
+        assert_eq!(loaded.build_version(), Some("X31.2.3"));
+        assert_eq!(loaded.rc4(), original.rc4());
+        assert_eq!(loaded.to_internal(1), Some(PacketType::Hello));
+        assert_eq!(loaded.to_internal(0), Some(PacketType::Failure));
+        assert_eq!(loaded.get_map().len(), 2);
+
+        let invalid = [
+            r#"{ "build_version": null, "rc4": "ab", "packets": {} }"#,
+            r#"{ "build_version": null, "rc4": "", "packets": { "NotAPacket": 1 } }"#,
+        ];
+
+        for text in invalid.iter() {
+            assert!(Mappings::from_reader(text.as_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_mappings_diff() {
+        let old = mappings(&[
+            (0, PacketType::Failure),
+            (1, PacketType::Hello),
+            (2, PacketType::Ping),
+        ]);
+        let new = mappings(&[
+            (0, PacketType::Failure),
+            (3, PacketType::Hello),
+            (4, PacketType::Pong),
+        ]);
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![(PacketType::Pong, 4)]);
+        assert_eq!(diff.removed, vec![(PacketType::Ping, 2)]);
+        assert_eq!(diff.renumbered, vec![(PacketType::Hello, 1, 3)]);
+
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! `Arbitrary` implementations for packet data, used for property-based
+//! testing. Only available with the `quickcheck` feature.
+//!
+//! Generated values are always valid for their binary representation, so
+//! encoding and then decoding them should produce an identical value.
+
+use super::data::*;
+use super::server::{EnemyShoot, Pic};
+use quickcheck::{Arbitrary, Gen};
+
+impl Arbitrary for WorldPosData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            x: Arbitrary::arbitrary(g),
+            y: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for GroundTileData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            x: Arbitrary::arbitrary(g),
+            y: Arbitrary::arbitrary(g),
+            tile: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for MoveRecord {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            time: Arbitrary::arbitrary(g),
+            x: Arbitrary::arbitrary(g),
+            y: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for ObjectData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            object_type: Arbitrary::arbitrary(g),
+            status: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for ObjectStatusData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            object_id: Arbitrary::arbitrary(g),
+            pos: Arbitrary::arbitrary(g),
+            stats: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for QuestData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            id: Arbitrary::arbitrary(g),
+            name: Arbitrary::arbitrary(g),
+            description: Arbitrary::arbitrary(g),
+            category: Arbitrary::arbitrary(g),
+            requirements: Arbitrary::arbitrary(g),
+            rewards: Arbitrary::arbitrary(g),
+            completed: Arbitrary::arbitrary(g),
+            item_of_choice: Arbitrary::arbitrary(g),
+            repeatable: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for SlotObjectData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            object_id: Arbitrary::arbitrary(g),
+            slot_id: Arbitrary::arbitrary(g),
+            object_type: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for TradeItem {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            item: Arbitrary::arbitrary(g),
+            slot_type: Arbitrary::arbitrary(g),
+            tradeable: Arbitrary::arbitrary(g),
+            included: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
+/// Generates stats of any known or unknown type. The values of unknown stats
+/// are always integers, matching the default `UnknownStatGuess`
+/// in `DecodeOptions`.
+impl Arbitrary for StatData {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let id = g.next_u32() as u8;
+
+        match StatType::from_byte(id) {
+            Some(typ) if typ.is_string() => StatData::String(typ, Arbitrary::arbitrary(g)),
+            Some(typ) => StatData::Integer(typ, Arbitrary::arbitrary(g)),
+            None => StatData::Unknown(id, StatValue::Integer(Arbitrary::arbitrary(g))),
+        }
+    }
+}
+
+/// The optional fields are only encoded when present, so `angle_inc` is only
+/// generated along with `num_shots`
+impl Arbitrary for EnemyShoot {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let (num_shots, angle_inc) = match g.next_u32() % 3 {
+            0 => (None, None),
+            1 => (Some(Arbitrary::arbitrary(g)), None),
+            _ => (Some(Arbitrary::arbitrary(g)), Some(Arbitrary::arbitrary(g))),
+        };
+
+        Self {
+            bullet_id: Arbitrary::arbitrary(g),
+            owner_id: Arbitrary::arbitrary(g),
+            bullet_type: Arbitrary::arbitrary(g),
+            starting_pos: Arbitrary::arbitrary(g),
+            angle: Arbitrary::arbitrary(g),
+            damage: Arbitrary::arbitrary(g),
+            num_shots,
+            angle_inc,
+        }
+    }
+}
+
+/// Generates small images with exactly four bytes of bitmap data per pixel
+impl Arbitrary for Pic {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let w = g.next_u32() % 16;
+        let h = g.next_u32() % 16;
+        let bitmap_data = (0..w * h * 4).map(|_| Arbitrary::arbitrary(g)).collect();
+
+        Self { w, h, bitmap_data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::Adapter;
+    use crate::packets::{Packet, PacketType, Side};
+    use crate::prelude::*;
+    use quickcheck::quickcheck;
+
+    /// Encode and then decode the given value
+    fn round_trip<T: Adapter>(value: &T) -> T {
+        let mut buf = vec![];
+        value.put_be(&mut buf).expect("encoding error");
+        assert_eq!(value.encoded_len(), buf.len(), "wrong encoded length");
+
+        let mut reader = &buf[..];
+        let decoded = T::get_be(&mut reader).expect("decoding error");
+        assert!(reader.is_empty(), "bytes left over");
+
+        decoded
+    }
+
+    quickcheck! {
+        fn prop_object_status_round_trip(status: ObjectStatusData) -> bool {
+            round_trip(&status) == status
+        }
+
+        fn prop_quest_round_trip(quest: QuestData) -> bool {
+            round_trip(&quest) == quest
+        }
+
+        fn prop_trade_item_round_trip(item: TradeItem) -> bool {
+            round_trip(&item) == item
+        }
+
+        fn prop_packet_round_trip(packet: Packet) -> bool {
+            let mut buf = vec![];
+            packet.to_bytes(&mut buf).expect("encoding error");
+            assert_eq!(packet.encoded_len(), buf.len(), "wrong encoded length");
+
+            match (&packet, packet.get_type()) {
+                (Packet::Unknown { payload, .. }, _) => &buf == payload,
+                (_, Some(typ)) => {
+                    Packet::from_bytes(typ, typ.side(), &buf).expect("decoding error") == packet
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        fn prop_pic_layout(pic: Pic) -> bool {
+            let mut expected = vec![];
+            expected.extend_from_slice(&pic.w.to_be_bytes());
+            expected.extend_from_slice(&pic.h.to_be_bytes());
+            expected.extend_from_slice(&pic.bitmap_data);
+
+            let mut buf = vec![];
+            pic.put_be(&mut buf).expect("encoding error");
+
+            let decoded = Packet::from_bytes(PacketType::Pic, Side::Server, &expected)
+                .expect("decoding error");
+
+            buf == expected && decoded == Packet::Pic(pic)
+        }
+    }
+}
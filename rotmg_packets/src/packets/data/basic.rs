@@ -3,68 +3,68 @@
 #![allow(missing_docs)]
 
 use super::stat::StatData;
-use crate::adapter::{Adapter, Result, RLE};
-use bytes::{Buf, BufMut};
+use crate::adapter::{Adapter, RLE};
+use crate::prelude::*;
+use crate::schema::Schema;
 use serde::{Deserialize, Serialize};
 
-macro_rules! define_data {
-    ($name:ident {
-        $(
-            $fieldname:ident: $fieldtype:ty
-        ),* $(,)?
-    }) => {
-        #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
-        pub struct $name {
-            $(
-                pub $fieldname: $fieldtype
-            ),*
-        }
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct GroundTileData {
+    pub x: u16,
+    pub y: u16,
+    pub tile: u16,
+}
 
-        impl Adapter for $name {
-            fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
-                $( let $fieldname = Adapter::get_be(bytes)?; )*
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct MoveRecord {
+    pub time: u32,
+    pub x: f32,
+    pub y: f32,
+}
 
-                Ok(Self { $( $fieldname ),* })
-            }
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct ObjectData {
+    pub object_type: u16,
+    pub status: ObjectStatusData,
+}
 
-            fn put_be(&self, bytes: &mut dyn BufMut) -> Result<()> {
-                let Self { $( $fieldname ),* } = self;
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct ObjectStatusData {
+    pub object_id: u32,
+    pub pos: WorldPosData,
+    pub stats: RLE<Vec<StatData>>,
+}
 
-                $( $fieldname.put_be(bytes)?; )*
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct QuestData {
+    pub id: RLE<String>,
+    pub name: RLE<String>,
+    pub description: RLE<String>,
+    pub category: u32,
+    pub requirements: RLE<Vec<u32>>,
+    pub rewards: RLE<Vec<u32>>,
+    pub completed: bool,
+    pub item_of_choice: bool,
+    pub repeatable: bool,
+}
 
-                Ok(())
-            }
-        }
-    };
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct SlotObjectData {
+    pub object_id: u32,
+    pub slot_id: u8,
+    pub object_type: u32,
+}
 
-    ($(
-        $name: ident {
-            $(
-                $fieldname:ident: $fieldtype:ty
-            ),* $(,)?
-        }
-    ),* $(,)?) => {
-        $(define_data! { $name { $($fieldname: $fieldtype),* } })*
-    }
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct TradeItem {
+    pub item: u32,
+    pub slot_type: u32,
+    pub tradeable: bool,
+    pub included: bool,
 }
 
-define_data! {
-    GroundTileData { x: u16, y: u16, tile: u16 },
-    MoveRecord { time: u32, x: f32, y: f32 },
-    ObjectData { object_type: u16, status: ObjectStatusData },
-    ObjectStatusData { object_id: u32, pos: WorldPosData, stats: RLE<Vec<StatData>> },
-    QuestData {
-        id: RLE<String>,
-        name: RLE<String>,
-        description: RLE<String>,
-        category: u32,
-        requirements: RLE<Vec<u32>>,
-        rewards: RLE<Vec<u32>>,
-        completed: bool,
-        item_of_choice: bool,
-        repeatable: bool
-    },
-    SlotObjectData { object_id: u32, slot_id: u8, object_type: u32 },
-    TradeItem { item: u32, slot_type: u32, tradeable: bool, included: bool },
-    WorldPosData { x: f32, y: f32 }
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Adapter, Schema)]
+pub struct WorldPosData {
+    pub x: f32,
+    pub y: f32,
 }
@@ -0,0 +1,179 @@
+//! Enums for result codes and other values which are sent as integers
+
+use crate::adapter::{Adapter, Result};
+use bytes::{Buf, BufMut};
+use core::result::Result as StdResult;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Define enums for integer codes. Each enum has an additional `Other` variant
+/// for unrecognized values, and is represented as the underlying integer type
+/// both in binary form and when using serde. With the `quickcheck` feature,
+/// values are generated from arbitrary integers.
+macro_rules! define_codes {
+    (
+        $(
+            $( #[$meta:meta] )*
+            $name:ident : $repr:ty {
+                $( $variant:ident = $value:literal ),* $(,)?
+            }
+        ),* $(,)?
+    ) => {
+        $(
+            $( #[$meta] )*
+            #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+            #[allow(missing_docs)]
+            pub enum $name {
+                $( $variant, )*
+
+                /// A value with no known meaning
+                Other($repr),
+            }
+
+            impl From<$repr> for $name {
+                fn from(value: $repr) -> Self {
+                    match value {
+                        $( $value => $name::$variant, )*
+                        other => $name::Other(other),
+                    }
+                }
+            }
+
+            impl From<$name> for $repr {
+                fn from(code: $name) -> Self {
+                    match code {
+                        $( $name::$variant => $value, )*
+                        $name::Other(other) => other,
+                    }
+                }
+            }
+
+            impl Adapter for $name {
+                fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+                    <$repr>::get_be(bytes).map(Self::from)
+                }
+
+                fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
+                    <$repr>::from(*self).put_be(buffer)
+                }
+
+                fn encoded_len(&self) -> usize {
+                    <$repr>::from(*self).encoded_len()
+                }
+            }
+
+            impl crate::schema::Schema for $name {
+                const WIRE: crate::schema::WireType = <$repr as crate::schema::Schema>::WIRE;
+            }
+
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+                    <$repr>::from(*self).serialize(serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> StdResult<Self, D::Error> {
+                    <$repr>::deserialize(deserializer).map(Self::from)
+                }
+            }
+
+            #[cfg(feature = "quickcheck")]
+            impl ::quickcheck::Arbitrary for $name {
+                fn arbitrary<G: ::quickcheck::Gen>(g: &mut G) -> Self {
+                    Self::from(<$repr as ::quickcheck::Arbitrary>::arbitrary(g))
+                }
+            }
+        )*
+    };
+}
+
+define_codes! {
+    /// The result of a `Buy` request, sent in `BuyResult`
+    BuyResultCode: u32 {
+        Success = 0,
+        InvalidCharacter = 1,
+        ItemNotFound = 2,
+        NotEnoughGold = 3,
+        InventoryFull = 4,
+        TooLowRank = 5,
+        NotEnoughFame = 6,
+        PetFeedSuccess = 7,
+        UnknownError = 0xffff_ffff,
+    },
+
+    /// The reason for a `Failure`
+    FailureCode: u32 {
+        IncorrectVersion = 4,
+        BadKey = 5,
+        InvalidTeleportTarget = 6,
+        EmailVerificationNeeded = 7,
+        TeleportRealmBlock = 9,
+    },
+
+    /// The outcome of a trade, sent in `TradeDone`
+    TradeResultCode: u32 {
+        Successful = 0,
+        PlayerCanceled = 1,
+    },
+
+    /// The type of visual effect displayed by `ShowEffect`
+    EffectType: u8 {
+        Unknown = 0,
+        Heal = 1,
+        Teleport = 2,
+        Stream = 3,
+        Throw = 4,
+        Nova = 5,
+        Poison = 6,
+        Line = 7,
+        Burst = 8,
+        Flow = 9,
+        Ring = 10,
+        Lightning = 11,
+        Collapse = 12,
+        ConeBlast = 13,
+        Jitter = 14,
+        Flash = 15,
+        ThrowProjectile = 16,
+        Shocker = 17,
+        Shockee = 18,
+        RisingFury = 19,
+    },
+
+    /// The result of an inventory operation, sent in `InvResult`
+    InvResultCode: u32 {
+        Success = 0,
+    },
+
+    /// The type of a `GlobalNotification`. The meanings of these values
+    /// haven't been identified yet.
+    NotificationType: u32 {},
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(FailureCode::from(4), FailureCode::IncorrectVersion);
+        assert_eq!(FailureCode::from(1234), FailureCode::Other(1234));
+        assert_eq!(u8::from(EffectType::Nova), 5);
+
+        let mut buf = vec![];
+        BuyResultCode::NotEnoughGold.put_be(&mut buf).unwrap();
+        BuyResultCode::Other(42).put_be(&mut buf).unwrap();
+        assert_eq!(buf, vec![0, 0, 0, 3, 0, 0, 0, 42]);
+
+        let mut reader = &buf[..];
+        assert_eq!(
+            BuyResultCode::get_be(&mut reader).unwrap(),
+            BuyResultCode::NotEnoughGold
+        );
+        assert_eq!(
+            BuyResultCode::get_be(&mut reader).unwrap(),
+            BuyResultCode::Other(42)
+        );
+    }
+}
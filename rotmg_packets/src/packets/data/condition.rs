@@ -0,0 +1,242 @@
+//! Types representing condition effects, such as paralyzed or invulnerable
+
+use crate::adapter::{Adapter, Result};
+use bytes::{Buf, BufMut};
+use core::fmt::{Debug, Formatter, Result as FmtResult};
+use core::iter::FromIterator;
+use core::result::Result as StdResult;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+define_codes! {
+    /// A single condition effect, as sent in `SetCondition`
+    ConditionEffect: u8 {
+        Nothing = 0,
+        Dead = 1,
+        Quiet = 2,
+        Weak = 3,
+        Slowed = 4,
+        Sick = 5,
+        Dazed = 6,
+        Stunned = 7,
+        Blind = 8,
+        Hallucinating = 9,
+        Drunk = 10,
+        Confused = 11,
+        StunImmune = 12,
+        Invisible = 13,
+        Paralyzed = 14,
+        Speedy = 15,
+        Bleeding = 16,
+        ArmorBrokenImmune = 17,
+        Healing = 18,
+        Damaging = 19,
+        Berserk = 20,
+        Paused = 21,
+        Stasis = 22,
+        StasisImmune = 23,
+        Invincible = 24,
+        Invulnerable = 25,
+        Armored = 26,
+        ArmorBroken = 27,
+        Hexed = 28,
+        NinjaSpeedy = 29,
+        Unstable = 30,
+        Darkness = 31,
+        SlowedImmune = 32,
+        DazedImmune = 33,
+        ParalyzedImmune = 34,
+        Petrified = 35,
+        PetrifiedImmune = 36,
+        PetEffectIcon = 37,
+        Curse = 38,
+        CurseImmune = 39,
+        HpBoost = 40,
+        MpBoost = 41,
+        AttBoost = 42,
+        DefBoost = 43,
+        SpdBoost = 44,
+        VitBoost = 45,
+        WisBoost = 46,
+        DexBoost = 47,
+        Silenced = 48,
+        Exposed = 49,
+        Energized = 50,
+    },
+}
+
+/// The number of effects which are stored in `StatType::CONDITION_STAT`. Any
+/// further effects are stored in `StatType::NEW_CON_STAT`.
+const CONDITION_STAT_EFFECTS: u32 = 31;
+
+/// The highest effect ID which can be stored, filling the 31 bits used in
+/// `CONDITION_STAT` and the 32 bits of `NEW_CON_STAT`
+const MAX_EFFECT: u8 = 63;
+
+/// The bits of a `ConditionEffects` which represent storable effects
+const EFFECT_MASK: u64 = (1 << MAX_EFFECT) - 1;
+
+/// A set of condition effects, as sent in the `CONDITION_STAT` and
+/// `NEW_CON_STAT` stats.
+///
+/// Each effect (other than `ConditionEffect::Nothing`) is represented by a
+/// single bit. The first 31 effects are stored in `CONDITION_STAT`, and the
+/// rest are stored in `NEW_CON_STAT`, so effects with IDs above 63 can't be
+/// represented and are ignored.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct ConditionEffects(u64);
+
+impl ConditionEffects {
+    /// Create an empty set of condition effects
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Create a set of condition effects from the values of the
+    /// `CONDITION_STAT` and `NEW_CON_STAT` stats
+    pub fn from_stats(condition: i32, new_condition: i32) -> Self {
+        let condition = u64::from(condition as u32) & ((1 << CONDITION_STAT_EFFECTS) - 1);
+        let new_condition = u64::from(new_condition as u32) << CONDITION_STAT_EFFECTS;
+        Self(condition | new_condition)
+    }
+
+    /// Create a set of condition effects from the value of the
+    /// `CONDITION_STAT` stat alone
+    pub fn from_condition_stat(condition: i32) -> Self {
+        Self::from_stats(condition, 0)
+    }
+
+    /// Convert this set of condition effects to values for the
+    /// `CONDITION_STAT` and `NEW_CON_STAT` stats, respectively
+    pub fn to_stats(self) -> (i32, i32) {
+        let condition = self.0 & ((1 << CONDITION_STAT_EFFECTS) - 1);
+        let new_condition = self.0 >> CONDITION_STAT_EFFECTS;
+        (condition as i32, new_condition as u32 as i32)
+    }
+
+    /// Get the bit representing the given effect, if any
+    fn bit(effect: ConditionEffect) -> Option<u64> {
+        match u8::from(effect) {
+            id @ 1..=MAX_EFFECT => Some(1 << (id - 1)),
+            _ => None,
+        }
+    }
+
+    /// Check whether this set contains no effects
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check whether this set contains the given effect
+    pub fn contains(self, effect: ConditionEffect) -> bool {
+        match Self::bit(effect) {
+            Some(bit) => self.0 & bit != 0,
+            None => false,
+        }
+    }
+
+    /// Add the given effect to this set. Adding `ConditionEffect::Nothing` has
+    /// no effect.
+    pub fn insert(&mut self, effect: ConditionEffect) {
+        if let Some(bit) = Self::bit(effect) {
+            self.0 |= bit;
+        }
+    }
+
+    /// Remove the given effect from this set
+    pub fn remove(&mut self, effect: ConditionEffect) {
+        if let Some(bit) = Self::bit(effect) {
+            self.0 &= !bit;
+        }
+    }
+
+    /// Get an iterator over the effects in this set
+    pub fn iter(self) -> impl Iterator<Item = ConditionEffect> {
+        (1..=MAX_EFFECT)
+            .filter(move |id| self.0 & (1 << (id - 1)) != 0)
+            .map(ConditionEffect::from)
+    }
+}
+
+impl From<ConditionEffect> for ConditionEffects {
+    fn from(effect: ConditionEffect) -> Self {
+        let mut effects = Self::empty();
+        effects.insert(effect);
+        effects
+    }
+}
+
+impl FromIterator<ConditionEffect> for ConditionEffects {
+    fn from_iter<I: IntoIterator<Item = ConditionEffect>>(iter: I) -> Self {
+        let mut effects = Self::empty();
+        iter.into_iter().for_each(|e| effects.insert(e));
+        effects
+    }
+}
+
+impl Debug for ConditionEffects {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for ConditionEffects {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConditionEffects {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> StdResult<Self, D::Error> {
+        u64::deserialize(deserializer).map(|bits| Self(bits & EFFECT_MASK))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_condition_effects() {
+        // dead, slowed and paralyzed
+        let effects = ConditionEffects::from_condition_stat(0b10_0000_0000_1001);
+
+        assert!(effects.contains(ConditionEffect::Dead));
+        assert!(effects.contains(ConditionEffect::Slowed));
+        assert!(effects.contains(ConditionEffect::Paralyzed));
+        assert!(!effects.contains(ConditionEffect::Quiet));
+        assert!(!effects.contains(ConditionEffect::Nothing));
+
+        assert_eq!(
+            effects.iter().collect::<Vec<_>>(),
+            vec![
+                ConditionEffect::Dead,
+                ConditionEffect::Slowed,
+                ConditionEffect::Paralyzed
+            ]
+        );
+
+        // darkness is the last effect in the first stat, slowed immune is the
+        // first effect in the second
+        let effects = ConditionEffects::from_stats(1 << 30, 1);
+        assert!(effects.contains(ConditionEffect::Darkness));
+        assert!(effects.contains(ConditionEffect::SlowedImmune));
+        assert_eq!(effects.to_stats(), (1 << 30, 1));
+
+        // the last bit of the second stat is the highest effect that fits
+        let top = ConditionEffect::from(63);
+        let effects = ConditionEffects::from(top);
+        assert_eq!(effects.to_stats(), (0, i32::MIN));
+        assert_eq!(ConditionEffects::from_stats(0, i32::MIN), effects);
+        assert_eq!(effects.iter().collect::<Vec<_>>(), vec![top]);
+        assert!(ConditionEffects::from(ConditionEffect::from(64)).is_empty());
+
+        let mut effects = ConditionEffects::from(ConditionEffect::Invulnerable);
+        effects.insert(ConditionEffect::Energized);
+        effects.remove(ConditionEffect::Invulnerable);
+        assert_eq!(
+            effects.iter().collect::<Vec<_>>(),
+            vec![ConditionEffect::Energized]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! A structured model of the stats of an entity, built from `StatData`
+
+use super::basic::ObjectStatusData;
+use super::condition::ConditionEffects;
+use super::stat::{StatData, StatType, StatValue};
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// A type which may be stored as the value of a `StatData`
+trait FieldValue: Sized {
+    /// Get the value of the given stat, if it's of the right type
+    fn from_stat(stat: &StatData) -> Option<Self>;
+
+    /// Convert this value to a stat of the given type
+    fn to_stat(&self, typ: StatType) -> StatData;
+}
+
+impl FieldValue for i32 {
+    fn from_stat(stat: &StatData) -> Option<Self> {
+        match stat {
+            StatData::Integer(_, value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn to_stat(&self, typ: StatType) -> StatData {
+        StatData::Integer(typ, *self)
+    }
+}
+
+impl FieldValue for String {
+    fn from_stat(stat: &StatData) -> Option<Self> {
+        match stat {
+            StatData::String(_, value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_stat(&self, typ: StatType) -> StatData {
+        StatData::String(typ, self.clone())
+    }
+}
+
+/// Update the given field with the value of the given stat, ignoring stats
+/// with the wrong type of value
+fn update<T: FieldValue>(field: &mut Option<T>, stat: &StatData) {
+    if let Some(value) = T::from_stat(stat) {
+        *field = Some(value);
+    }
+}
+
+/// The stats used for each inventory slot, in order
+const INVENTORY_STATS: [StatType; 12] = [
+    StatType::INVENTORY_0_STAT,
+    StatType::INVENTORY_1_STAT,
+    StatType::INVENTORY_2_STAT,
+    StatType::INVENTORY_3_STAT,
+    StatType::INVENTORY_4_STAT,
+    StatType::INVENTORY_5_STAT,
+    StatType::INVENTORY_6_STAT,
+    StatType::INVENTORY_7_STAT,
+    StatType::INVENTORY_8_STAT,
+    StatType::INVENTORY_9_STAT,
+    StatType::INVENTORY_10_STAT,
+    StatType::INVENTORY_11_STAT,
+];
+
+/// The stats used for each backpack slot, in order
+const BACKPACK_STATS: [StatType; 8] = [
+    StatType::BACKPACK_0_STAT,
+    StatType::BACKPACK_1_STAT,
+    StatType::BACKPACK_2_STAT,
+    StatType::BACKPACK_3_STAT,
+    StatType::BACKPACK_4_STAT,
+    StatType::BACKPACK_5_STAT,
+    StatType::BACKPACK_6_STAT,
+    StatType::BACKPACK_7_STAT,
+];
+
+macro_rules! define_entity_stats {
+    (
+        $(
+            $field:ident : $type:ty = $stat:ident
+        ),* $(,)?
+    ) => {
+        /// A snapshot of the stats of an entity.
+        ///
+        /// The server only sends stats which have changed, so a snapshot can
+        /// be kept up to date by applying each set of stats received for the
+        /// entity. Stats which haven't been received yet are `None`.
+        #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+        #[allow(missing_docs)]
+        pub struct EntityStats {
+            $( pub $field: Option<$type>, )*
+
+            /// The item in each inventory slot, where -1 indicates an empty
+            /// slot
+            pub inventory: [Option<i32>; 12],
+
+            /// The item in each backpack slot, where -1 indicates an empty
+            /// slot
+            pub backpack: [Option<i32>; 8],
+
+            /// The values of any stats with unknown types, by id
+            pub unknown: BTreeMap<u8, StatValue>,
+        }
+
+        impl EntityStats {
+            /// Update this snapshot with a single stat
+            fn apply_one(&mut self, stat: &StatData) {
+                let typ = match stat {
+                    StatData::Unknown(id, value) => {
+                        self.unknown.insert(*id, value.clone());
+                        return;
+                    }
+                    _ => stat.stat_type(),
+                };
+
+                if let Some(slot) = INVENTORY_STATS.iter().position(|&t| Some(t) == typ) {
+                    update(&mut self.inventory[slot], stat);
+                } else if let Some(slot) = BACKPACK_STATS.iter().position(|&t| Some(t) == typ) {
+                    update(&mut self.backpack[slot], stat);
+                } else {
+                    match typ {
+                        $( Some(StatType::$stat) => update(&mut self.$field, stat), )*
+                        _ => {}
+                    }
+                }
+            }
+
+            /// Convert this snapshot back to a list of stats, containing each
+            /// stat which has a value
+            pub fn to_stats(&self) -> Vec<StatData> {
+                let mut stats = vec![];
+
+                $(
+                    if let Some(value) = &self.$field {
+                        stats.push(value.to_stat(StatType::$stat));
+                    }
+                )*
+
+                let slots = INVENTORY_STATS
+                    .iter()
+                    .zip(self.inventory.iter())
+                    .chain(BACKPACK_STATS.iter().zip(self.backpack.iter()));
+
+                for (&typ, value) in slots {
+                    if let Some(value) = value {
+                        stats.push(value.to_stat(typ));
+                    }
+                }
+
+                for (&id, value) in self.unknown.iter() {
+                    stats.push(StatData::Unknown(id, value.clone()));
+                }
+
+                stats
+            }
+        }
+    };
+}
+
+define_entity_stats! {
+    max_hp: i32 = MAX_HP_STAT,
+    hp: i32 = HP_STAT,
+    size: i32 = SIZE_STAT,
+    max_mp: i32 = MAX_MP_STAT,
+    mp: i32 = MP_STAT,
+    next_level_exp: i32 = NEXT_LEVEL_EXP_STAT,
+    exp: i32 = EXP_STAT,
+    level: i32 = LEVEL_STAT,
+    attack: i32 = ATTACK_STAT,
+    defense: i32 = DEFENSE_STAT,
+    speed: i32 = SPEED_STAT,
+    vitality: i32 = VITALITY_STAT,
+    wisdom: i32 = WISDOM_STAT,
+    dexterity: i32 = DEXTERITY_STAT,
+    condition: i32 = CONDITION_STAT,
+    num_stars: i32 = NUM_STARS_STAT,
+    name: String = NAME_STAT,
+    tex1: i32 = TEX1_STAT,
+    tex2: i32 = TEX2_STAT,
+    merchandise_type: i32 = MERCHANDISE_TYPE_STAT,
+    credits: i32 = CREDITS_STAT,
+    merchandise_price: i32 = MERCHANDISE_PRICE_STAT,
+    active: i32 = ACTIVE_STAT,
+    account_id: String = ACCOUNT_ID_STAT,
+    fame: i32 = FAME_STAT,
+    merchandise_currency: i32 = MERCHANDISE_CURRENCY_STAT,
+    connect: i32 = CONNECT_STAT,
+    merchandise_count: i32 = MERCHANDISE_COUNT_STAT,
+    merchandise_mins_left: i32 = MERCHANDISE_MINS_LEFT_STAT,
+    merchandise_discount: i32 = MERCHANDISE_DISCOUNT_STAT,
+    merchandise_rank_req: i32 = MERCHANDISE_RANK_REQ_STAT,
+    max_hp_boost: i32 = MAX_HP_BOOST_STAT,
+    max_mp_boost: i32 = MAX_MP_BOOST_STAT,
+    attack_boost: i32 = ATTACK_BOOST_STAT,
+    defense_boost: i32 = DEFENSE_BOOST_STAT,
+    speed_boost: i32 = SPEED_BOOST_STAT,
+    vitality_boost: i32 = VITALITY_BOOST_STAT,
+    wisdom_boost: i32 = WISDOM_BOOST_STAT,
+    dexterity_boost: i32 = DEXTERITY_BOOST_STAT,
+    owner_account_id: String = OWNER_ACCOUNT_ID_STAT,
+    rank_required: i32 = RANK_REQUIRED_STAT,
+    name_chosen: i32 = NAME_CHOSEN_STAT,
+    curr_fame: i32 = CURR_FAME_STAT,
+    next_class_quest_fame: i32 = NEXT_CLASS_QUEST_FAME_STAT,
+    legendary_rank: i32 = LEGENDARY_RANK_STAT,
+    sink_level: i32 = SINK_LEVEL_STAT,
+    alt_texture: i32 = ALT_TEXTURE_STAT,
+    guild_name: String = GUILD_NAME_STAT,
+    guild_rank: i32 = GUILD_RANK_STAT,
+    breath: i32 = BREATH_STAT,
+    xp_boosted: i32 = XP_BOOSTED_STAT,
+    xp_timer: i32 = XP_TIMER_STAT,
+    ld_timer: i32 = LD_TIMER_STAT,
+    lt_timer: i32 = LT_TIMER_STAT,
+    health_potion_stack: i32 = HEALTH_POTION_STACK_STAT,
+    magic_potion_stack: i32 = MAGIC_POTION_STACK_STAT,
+    has_backpack: i32 = HASBACKPACK_STAT,
+    texture: i32 = TEXTURE_STAT,
+    pet_instance_id: i32 = PET_INSTANCEID_STAT,
+    pet_name: String = PET_NAME_STAT,
+    pet_type: i32 = PET_TYPE_STAT,
+    pet_rarity: i32 = PET_RARITY_STAT,
+    pet_max_ability_power: i32 = PET_MAXABILITYPOWER_STAT,
+    pet_family: i32 = PET_FAMILY_STAT,
+    pet_first_ability_point: i32 = PET_FIRSTABILITY_POINT_STAT,
+    pet_second_ability_point: i32 = PET_SECONDABILITY_POINT_STAT,
+    pet_third_ability_point: i32 = PET_THIRDABILITY_POINT_STAT,
+    pet_first_ability_power: i32 = PET_FIRSTABILITY_POWER_STAT,
+    pet_second_ability_power: i32 = PET_SECONDABILITY_POWER_STAT,
+    pet_third_ability_power: i32 = PET_THIRDABILITY_POWER_STAT,
+    pet_first_ability_type: i32 = PET_FIRSTABILITY_TYPE_STAT,
+    pet_second_ability_type: i32 = PET_SECONDABILITY_TYPE_STAT,
+    pet_third_ability_type: i32 = PET_THIRDABILITY_TYPE_STAT,
+    new_condition: i32 = NEW_CON_STAT,
+    fortune_token: i32 = FORTUNE_TOKEN_STAT,
+    supporter_points: i32 = SUPPORTER_POINTS_STAT,
+    supporter: i32 = SUPPORTER_STAT,
+}
+
+impl EntityStats {
+    /// Create an empty snapshot, with no stats
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a snapshot from the stats of the given object
+    pub fn from_status(status: &ObjectStatusData) -> Self {
+        let mut stats = Self::new();
+        stats.apply(&status.stats);
+        stats
+    }
+
+    /// Update this snapshot with the given stats, overwriting any previous
+    /// values
+    pub fn apply(&mut self, stats: &[StatData]) {
+        stats.iter().for_each(|s| self.apply_one(s));
+    }
+
+    /// Get the condition effects applied to this entity
+    pub fn conditions(&self) -> ConditionEffects {
+        ConditionEffects::from_stats(self.condition.unwrap_or(0), self.new_condition.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::data::ConditionEffect;
+
+    #[test]
+    fn test_entity_stats() {
+        let mut stats = EntityStats::new();
+
+        stats.apply(&[
+            StatData::Integer(StatType::HP_STAT, 100),
+            StatData::Integer(StatType::MAX_HP_STAT, 200),
+            StatData::String(StatType::NAME_STAT, "Player".to_owned()),
+            StatData::Integer(StatType::INVENTORY_3_STAT, 2591),
+        ]);
+
+        stats.apply(&[
+            StatData::Integer(StatType::HP_STAT, 50),
+            StatData::Integer(StatType::BACKPACK_7_STAT, -1),
+            StatData::Integer(StatType::CONDITION_STAT, 1 << 13),
+            StatData::Unknown(200, StatValue::Integer(7)),
+        ]);
+
+        assert_eq!(stats.hp, Some(50));
+        assert_eq!(stats.max_hp, Some(200));
+        assert_eq!(stats.name, Some("Player".to_owned()));
+        assert_eq!(stats.inventory[3], Some(2591));
+        assert_eq!(stats.inventory[4], None);
+        assert_eq!(stats.backpack[7], Some(-1));
+        assert!(stats.conditions().contains(ConditionEffect::Paralyzed));
+        assert_eq!(stats.unknown.get(&200), Some(&StatValue::Integer(7)));
+
+        // converting back to stats should produce an equivalent snapshot
+        let mut copy = EntityStats::new();
+        copy.apply(&stats.to_stats());
+        assert_eq!(copy, stats);
+    }
+}
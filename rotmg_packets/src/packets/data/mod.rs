@@ -1,7 +1,14 @@
 //! Data types used in packets
 
 mod basic;
+#[macro_use]
+mod codes;
+mod condition;
+mod entity;
 mod stat;
 
 pub use self::basic::*;
+pub use self::codes::*;
+pub use self::condition::*;
+pub use self::entity::*;
 pub use self::stat::*;
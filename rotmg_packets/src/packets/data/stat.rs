@@ -2,7 +2,10 @@
 
 #![allow(missing_docs)]
 
-use crate::adapter::{Adapter, Error, Result, RLE};
+use crate::adapter::{Adapter, Error, Result, Sequence};
+use crate::packets::DecodeOptions;
+use crate::prelude::*;
+use crate::schema::{CustomSchema, Schema, WireType};
 use bytes::{Buf, BufMut};
 use serde::{Deserialize, Serialize};
 
@@ -30,8 +33,8 @@ macro_rules! define_stat_types {
         }
 
         impl StatType {
-            const VALID_TYPES: [Option<StatType>; 255] = {
-                let mut array = [None; 255];
+            const VALID_TYPES: [Option<StatType>; 256] = {
+                let mut array = [None; 256];
                 $(
                     array[$value] = Some(StatType::$name);
                 )*
@@ -162,7 +165,7 @@ define_stat_types! {
 }
 
 impl Adapter for StatType {
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
         let typ = u8::get_be(bytes)?;
 
         if let Some(typ) = StatType::from_byte(typ) {
@@ -172,37 +175,109 @@ impl Adapter for StatType {
         }
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
         self.to_byte().put_be(buffer)
     }
+
+    fn encoded_len(&self) -> usize {
+        1
+    }
+}
+
+impl Schema for StatType {
+    const WIRE: WireType = WireType::U8;
+}
+
+/// How the value of a stat with an unknown type should be decoded, as set by
+/// `DecodeOptions::unknown_stats`. Since the type of the value can't be
+/// determined, this guess may be wrong, and decoding may fail or produce
+/// garbage.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum UnknownStatGuess {
+    /// Decode the value as an integer (the default)
+    #[default]
+    Integer,
+    /// Decode the value as a string
+    String,
+}
+
+impl UnknownStatGuess {
+    /// Check whether the value of a stat with the given ID is a string,
+    /// using this guess if its type is unknown
+    pub(crate) fn is_string(self, id: u8) -> bool {
+        match StatType::from_byte(id) {
+            Some(typ) => typ.is_string(),
+            None => self == UnknownStatGuess::String,
+        }
+    }
+}
+
+/// The value of a stat with an unknown type
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum StatValue {
+    String(String),
+    Integer(i32),
 }
 
-/// Stat data, either of string or integer type
+/// Stat data, either of string or integer type.
+///
+/// Stats with types that aren't known are decoded as `StatData::Unknown`, with
+/// the value decoded according to the `unknown_stats` decode option.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum StatData {
     String(StatType, String),
     Integer(StatType, i32),
+    Unknown(u8, StatValue),
+}
+
+impl StatData {
+    /// Get the type of this stat, or `None` if the type is unknown
+    pub fn stat_type(&self) -> Option<StatType> {
+        match self {
+            StatData::String(typ, _) | StatData::Integer(typ, _) => Some(*typ),
+            StatData::Unknown(_, _) => None,
+        }
+    }
+
+    /// Get the byte representing the type of this stat
+    pub fn id(&self) -> u8 {
+        match self {
+            StatData::String(typ, _) | StatData::Integer(typ, _) => typ.to_byte(),
+            StatData::Unknown(id, _) => *id,
+        }
+    }
 }
 
 impl Adapter for StatData {
-    fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
-        let typ = StatType::get_be(bytes)?;
-
-        if typ.is_string() {
-            Ok(StatData::String(
-                typ,
-                RLE::<String>::get_be(bytes)?.unwrap(),
-            ))
+    fn get_be<B: Buf + ?Sized>(bytes: &mut B) -> Result<Self> {
+        Self::get_be_with(bytes, &DecodeOptions::default())
+    }
+
+    fn get_be_with<B: Buf + ?Sized>(bytes: &mut B, options: &DecodeOptions) -> Result<Self> {
+        let id = u8::get_be(bytes)?;
+        let typ = StatType::from_byte(id);
+
+        // the type of the value depends on the type of the stat
+        let value = if options.unknown_stats.is_string(id) {
+            String::get_prefixed::<u16, _>(bytes).map(StatValue::String)
         } else {
-            Ok(StatData::Integer(typ, i32::get_be(bytes)?))
+            i32::get_be(bytes).map(StatValue::Integer)
         }
+        .map_err(|e| e.in_field("value", bytes.remaining()))?;
+
+        Ok(match (typ, value) {
+            (Some(typ), StatValue::String(str)) => StatData::String(typ, str),
+            (Some(typ), StatValue::Integer(int)) => StatData::Integer(typ, int),
+            (None, value) => StatData::Unknown(id, value),
+        })
     }
 
-    fn put_be(&self, buffer: &mut dyn BufMut) -> Result<()> {
+    fn put_be<B: BufMut + ?Sized>(&self, buffer: &mut B) -> Result<()> {
         match self {
             StatData::String(typ, str) => {
                 if typ.is_string() {
-                    RLE::<String, u16>::new(str.clone()).put_be(buffer)
+                    typ.put_be(buffer)?;
+                    str.put_prefixed::<u16, _>(buffer)
                 } else {
                     Err(Error::InvalidData(format!(
                         "Stats of type {:?} should be strings",
@@ -212,6 +287,7 @@ impl Adapter for StatData {
             }
             StatData::Integer(typ, int) => {
                 if !typ.is_string() {
+                    typ.put_be(buffer)?;
                     int.put_be(buffer)
                 } else {
                     Err(Error::InvalidData(format!(
@@ -220,6 +296,103 @@ impl Adapter for StatData {
                     )))
                 }
             }
+            StatData::Unknown(id, value) => {
+                id.put_be(buffer)?;
+
+                match value {
+                    StatValue::String(str) => str.put_prefixed::<u16, _>(buffer),
+                    StatValue::Integer(int) => int.put_be(buffer),
+                }
+            }
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let value_len = match self {
+            StatData::String(_, str) | StatData::Unknown(_, StatValue::String(str)) => {
+                str.prefixed_len::<u16>()
+            }
+            StatData::Integer(_, int) | StatData::Unknown(_, StatValue::Integer(int)) => {
+                int.encoded_len()
+            }
+        };
+
+        1 + value_len
+    }
+}
+
+/// The type of the value depends on the type of the stat
+impl Schema for StatData {
+    const WIRE: WireType = WireType::Custom(&CustomSchema::new::<StatData>(
+        "stat type (u8) followed by i32 or RLE<String>",
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stat_data() {
+        let stats = [
+            StatData::Integer(StatType::HP_STAT, 100),
+            StatData::String(StatType::NAME_STAT, "Player".to_owned()),
+            StatData::Unknown(200, StatValue::Integer(5)),
+        ];
+
+        let mut buf = vec![];
+        for stat in stats.iter() {
+            stat.put_be(&mut buf).expect("encoding error");
+        }
+
+        assert_eq!(
+            stats.iter().map(StatData::encoded_len).sum::<usize>(),
+            buf.len()
+        );
+
+        assert_eq!(&buf[..5], &[1, 0, 0, 0, 100]);
+        assert_eq!(&buf[5..8], &[31, 0, 6]);
+
+        let mut reader = &buf[..];
+        for stat in stats.iter() {
+            assert_eq!(
+                &StatData::get_be(&mut reader).expect("decoding error"),
+                stat
+            );
         }
+
+        assert!(StatData::String(StatType::HP_STAT, String::new())
+            .put_be(&mut vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_stat_guess() {
+        let stat = StatData::Unknown(200, StatValue::String("abc".to_owned()));
+        let mut buf = vec![];
+        stat.put_be(&mut buf).expect("encoding error");
+
+        // by default, the value of the unknown stat is decoded as an integer
+        let guessed = StatData::get_be(&mut &buf[..]).expect("decoding error");
+        assert_eq!(
+            guessed,
+            StatData::Unknown(200, StatValue::Integer(0x0003_6162))
+        );
+
+        // unless the options say otherwise
+        let options = DecodeOptions {
+            unknown_stats: UnknownStatGuess::String,
+            ..DecodeOptions::default()
+        };
+        let decoded = StatData::get_be_with(&mut &buf[..], &options).expect("decoding error");
+        assert_eq!(decoded, stat);
+
+        // known stats aren't affected by the guess
+        let mut buf = vec![];
+        StatData::Integer(StatType::HP_STAT, 5)
+            .put_be(&mut buf)
+            .expect("encoding error");
+        let decoded = StatData::get_be_with(&mut &buf[..], &options).expect("decoding error");
+        assert_eq!(decoded, StatData::Integer(StatType::HP_STAT, 5));
     }
 }
@@ -1,58 +1,152 @@
 //! Definitions of packet structures, adapters, and related types
 
 // re-export things
-pub use self::unified_definitions::{client, server, Packet, PacketType};
+pub use self::unified_definitions::{
+    client, server, ClientPacket, Packet, PacketType, ServerPacket,
+};
+
+use super::data::UnknownStatGuess;
+use super::DecodeError;
+use crate::adapter::Result;
+#[cfg(feature = "std")]
+use crate::mappings::Mappings;
+use bytes::BufMut;
+use core::convert::TryFrom;
+use core::result::Result as StdResult;
+use serde::{Deserialize, Serialize};
+
+/// A side of a connection, i.e. the sender or recipient of a packet
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Side {
+    /// The game client
+    Client,
+    /// The game server
+    Server,
+}
+
+/// How bytes left over after decoding the contents of a packet are treated.
+///
+/// Leftover bytes usually mean that the layout of a packet has changed (e.g.
+/// a new field was added to the end), so they shouldn't be silently ignored.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DecodeMode {
+    /// Fail with `DecodeError::TrailingBytes` when bytes are left over
+    #[default]
+    Strict,
+    /// Return any leftover bytes alongside the decoded packet
+    Lenient,
+}
+
+/// Options controlling how packets are decoded.
+///
+/// The options are passed down to the adapter of every value in a packet
+/// through `Adapter::get_be_with`, so that different connections in the same
+/// process can decode packets differently. A `DecodeMode` can be used wherever
+/// options are expected, with the defaults for everything else.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    /// How bytes left over after decoding a packet are treated
+    pub mode: DecodeMode,
+    /// How the values of stats with unknown types are decoded
+    pub unknown_stats: UnknownStatGuess,
+}
+
+impl From<DecodeMode> for DecodeOptions {
+    fn from(mode: DecodeMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+}
+
+/// A packet sent by a specific side of the connection, either `ClientPacket`
+/// or `ServerPacket`.
+///
+/// Using these types instead of `Packet` allows the direction of packets to be
+/// checked at compile time.
+pub trait DirectedPacket:
+    Sized + Into<Packet> + TryFrom<Packet, Error = Packet> + Send + 'static
+{
+    /// The side of the connection which sends these packets
+    const SENDER: Side;
+
+    /// Get the `PacketType` of this packet, or `None` if this is an unknown
+    /// packet
+    fn get_type(&self) -> Option<PacketType>;
+
+    /// Get the ID used by the game for this packet, or `None` if there is no
+    /// mapping for its type
+    #[cfg(feature = "std")]
+    fn game_id(&self, mappings: &Mappings) -> Option<u8>;
+
+    /// Decode a packet of the given type from its binary contents, as with
+    /// `Packet::decode`
+    fn decode(
+        typ: PacketType,
+        contents: &[u8],
+        options: impl Into<DecodeOptions>,
+    ) -> StdResult<(Self, &[u8]), DecodeError>;
+
+    /// Write the binary contents of this packet to the given buffer
+    fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) -> Result<()>;
+
+    /// Get the exact number of bytes `to_bytes` would write for this packet
+    fn encoded_len(&self) -> usize;
+}
 
-/// Define the structure of a packet
+/// Define the structure of a packet, along with any additional derives
 macro_rules! define_structure {
     (
-        $name:ident { $(
+        [ $( $derive:ident ),* ] $name:ident { $(
+            $( #[$fieldattr:meta] )*
             $fieldname:ident : $fieldtype:ty
         ),* $(,)? }
     ) => {
-        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize $( , $derive )*)]
         #[allow(missing_docs)]
         pub struct $name {
             $(
+                $( #[$fieldattr] )*
                 pub $fieldname : $fieldtype
             ),*
         }
     };
 }
 
-/// Define an adapter for a packet
-macro_rules! define_adapter {
+/// Generate an `Arbitrary` implementation for a packet struct, generating each
+/// field independently
+macro_rules! define_arbitrary {
     (
         $name:ident { $(
+            $( #[$fieldattr:meta] )*
             $fieldname:ident : $fieldtype:ty
         ),* $(,)? }
     ) => {
-        impl Adapter for $name {
-            fn get_be(_bytes: &mut dyn Buf) -> Result<Self> {
-                $( let $fieldname = Adapter::get_be(_bytes)?; )*
-
-                Ok(Self { $( $fieldname ),* })
-            }
-
-            fn put_be(&self, _bytes: &mut dyn BufMut) -> Result<()> {
-                let Self { $( $fieldname ),* } = self;
-
-                $( $fieldname.put_be(_bytes)?; )*
-
-                Ok(())
+        #[cfg(feature = "quickcheck")]
+        impl Arbitrary for $name {
+            #[allow(unused_variables)]
+            fn arbitrary<G: Gen>(g: &mut G) -> Self {
+                $name { $( $fieldname: Arbitrary::arbitrary(g) ),* }
             }
         }
-    }
+    };
 }
 
-/// Define a single packet struct and (optionally) adapter
+/// Define a single packet struct, deriving its schema and an adapter unless it
+/// will be implemented manually, and generating an `Arbitrary` implementation unless
+/// the fields can't be generated independently
 macro_rules! define_single_packet {
     ($side:tt $name:ident (ManualAdapter) $fields:tt) => {
-        define_structure! { $name $fields }
+        define_structure! { [Schema] $name $fields }
+        define_arbitrary! { $name $fields }
+    };
+    ($side:tt $name:ident (ManualArbitrary) $fields:tt) => {
+        define_structure! { [Adapter, Schema] $name $fields }
     };
     ($side:tt $name:ident $fields:tt) => {
-        define_single_packet! { $side $name (ManualAdapter) $fields }
-        define_adapter! { $name $fields }
+        define_structure! { [Adapter, Schema] $name $fields }
+        define_arbitrary! { $name $fields }
     };
 }
 
@@ -66,15 +160,127 @@ macro_rules! is_server {
     };
 }
 
-/// Define the module for the given side
+/// Define the module and `DirectedPacket` enum for the given side
 macro_rules! define_side {
     (Client: $( $name:ident ),* $(,)?) => {
         /// Packets sent by the client
         pub mod client { $( pub use super::$name; )* }
+
+        define_directed_packet! {
+            /// A packet of any type sent by the client
+            ClientPacket: Client { $( $name ),* }
+        }
     };
     (Server: $( $name:ident ),* $(,)?) => {
         /// Packets sent by the server
         pub mod server { $( pub use super::$name; )* }
+
+        define_directed_packet! {
+            /// A packet of any type sent by the server
+            ServerPacket: Server { $( $name ),* }
+        }
+    };
+}
+
+/// Define an enum of the packets sent by one side, implementing
+/// `DirectedPacket`
+macro_rules! define_directed_packet {
+    (
+        $( #[$meta:meta] )*
+        $enum:ident : $side:ident { $( $name:ident ),* }
+    ) => {
+        $( #[$meta] )*
+        #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+        #[serde(tag = "type")]
+        #[allow(missing_docs, clippy::large_enum_variant)]
+        pub enum $enum {
+            $( $name($name), )*
+
+            /// A packet which has no `PacketType` mapped to its ID, or which
+            /// could not be decoded, as with `Packet::Unknown`
+            Unknown {
+                id: u8,
+                #[serde(with = "crate::text::hex_bytes")]
+                payload: Vec<u8>,
+            },
+        }
+
+        impl From<$enum> for Packet {
+            fn from(packet: $enum) -> Packet {
+                match packet {
+                    $( $enum::$name(p) => Packet::$name(p), )*
+                    $enum::Unknown { id, payload } => Packet::Unknown { id, payload },
+                }
+            }
+        }
+
+        impl TryFrom<Packet> for $enum {
+            type Error = Packet;
+
+            fn try_from(packet: Packet) -> StdResult<$enum, Packet> {
+                match packet {
+                    $( Packet::$name(p) => Ok($enum::$name(p)), )*
+                    Packet::Unknown { id, payload } => Ok($enum::Unknown { id, payload }),
+                    p => Err(p),
+                }
+            }
+        }
+
+        $(
+            impl From<$name> for $enum {
+                fn from(data: $name) -> $enum {
+                    $enum::$name(data)
+                }
+            }
+        )*
+
+        impl DirectedPacket for $enum {
+            const SENDER: Side = Side::$side;
+
+            fn get_type(&self) -> Option<PacketType> {
+                match self {
+                    $( $enum::$name(_) => Some(PacketType::$name), )*
+                    $enum::Unknown { .. } => None,
+                }
+            }
+
+            #[cfg(feature = "std")]
+            fn game_id(&self, mappings: &Mappings) -> Option<u8> {
+                match self {
+                    $enum::Unknown { id, .. } => Some(*id),
+                    p => mappings.to_game(p.get_type()?),
+                }
+            }
+
+            fn decode(
+                typ: PacketType,
+                contents: &[u8],
+                options: impl Into<DecodeOptions>,
+            ) -> StdResult<(Self, &[u8]), DecodeError> {
+                let (packet, trailing) = Packet::decode(typ, Side::$side, contents, options)?;
+                let packet = $enum::try_from(packet)
+                    .unwrap_or_else(|_| unreachable!("packet was decoded for the wrong side"));
+
+                Ok((packet, trailing))
+            }
+
+            fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) -> Result<()> {
+                match self {
+                    $( $enum::$name(p) => p.put_be(buf), )*
+                    $enum::Unknown { payload, .. } => {
+                        buf.put_slice(&payload[..]);
+                        Ok(())
+                    }
+                }
+            }
+
+            fn encoded_len(&self) -> usize {
+                match self {
+                    $( $enum::$name(p) => p.encoded_len(), )*
+                    $enum::Unknown { payload, .. } => payload.len(),
+                }
+            }
+        }
     };
 }
 
@@ -91,6 +297,7 @@ macro_rules! define_packets {
                 $(
                     $name: ident $( ( $adapterspec:tt ) )? {
                         $(
+                            $( #[$fieldattr:meta] )*
                             $fieldname:ident : $fieldtype:ty
                         ),* $(,)?
                     }
@@ -103,7 +310,7 @@ macro_rules! define_packets {
             $(
                 define_single_packet! {
                     $side $name $( ( $adapterspec ) )* {
-                        $( $fieldname : $fieldtype ),*
+                        $( $( #[$fieldattr] )* $fieldname : $fieldtype ),*
                     }
                 }
             )*
@@ -120,9 +327,19 @@ macro_rules! define_packets {
         pub enum Packet {
             $( // each side
                 $( // each packet
-                    $name($name)
-                ),*
-            ),*
+                    $name($name),
+                )*
+            )*
+
+            /// A packet which has no `PacketType` mapped to its ID, or which
+            /// could not be decoded. The ID used by the game and the binary
+            /// contents are kept as-is, so the packet can be re-encoded
+            /// exactly.
+            Unknown {
+                id: u8,
+                #[serde(with = "crate::text::hex_bytes")]
+                payload: Vec<u8>,
+            },
         }
 
         /// A compact (one byte) representation of the type of a packet.
@@ -213,32 +430,141 @@ macro_rules! define_packets {
                 self.try_into()
             }
 
-            /// Get the `PacketType` of this packet
-            pub fn get_type(&self) -> PacketType {
+            /// Get the `PacketType` of this packet, or `None` if this is a
+            /// `Packet::Unknown`
+            pub fn get_type(&self) -> Option<PacketType> {
                 match self {
                     $(
                         $(
-                            Packet::$name(_) => PacketType::$name
+                            Packet::$name(_) => Some(PacketType::$name),
+                        )*
+                    )*
+                    Packet::Unknown { .. } => None,
+                }
+            }
+
+            /// Decode a packet of the given type, sent by the given side of the
+            /// connection, from its binary contents.
+            ///
+            /// An error will be returned if packets of this type aren't sent by
+            /// the given side (`DecodeError::WrongSide`), if any bytes are left
+            /// over after decoding the packet (`DecodeError::TrailingBytes`),
+            /// or if the `Adapter` implementation for the packet fails
+            /// (`DecodeError::AdapterError`).
+            pub fn from_bytes(
+                typ: PacketType,
+                sender: Side,
+                contents: &[u8],
+            ) -> StdResult<Packet, DecodeError> {
+                Self::decode(typ, sender, contents, DecodeMode::Strict).map(|(packet, _)| packet)
+            }
+
+            /// Decode a packet like `from_bytes` using the given options,
+            /// which may just be a `DecodeMode`. On success, the packet is
+            /// returned along with the bytes left over after decoding, which
+            /// will always be empty in strict mode.
+            pub fn decode(
+                typ: PacketType,
+                sender: Side,
+                contents: &[u8],
+                options: impl Into<DecodeOptions>,
+            ) -> StdResult<(Packet, &[u8]), DecodeError> {
+                let options = options.into();
+
+                if typ.side() != sender {
+                    return Err(DecodeError::WrongSide { packet_type: typ, sender });
+                }
+
+                let mut buf = contents;
+
+                let packet = match typ {
+                    $(
+                        $(
+                            PacketType::$name => {
+                                $name::get_be_with(&mut buf, &options).map(Packet::$name)
+                            }
                         ),*
                     ),*
                 }
+                .map_err(|e| {
+                    e.in_field(typ.get_name(), buf.remaining())
+                        .with_offset(contents.len())
+                })?;
+
+                // the buffer has been advanced past the decoded contents
+                let trailing = buf;
+
+                if options.mode == DecodeMode::Strict && !trailing.is_empty() {
+                    Err(DecodeError::TrailingBytes {
+                        packet_type: typ,
+                        remaining: trailing.len(),
+                        hex: hex::encode(trailing),
+                    })
+                } else {
+                    Ok((packet, trailing))
+                }
             }
 
-            /// Create a packet from the given type and contents - should not be
-            /// used unless you really know what you're doing!
-            pub unsafe fn from_bytes(typ: PacketType, contents: &mut dyn Buf) -> Result<Packet> {
-                typ.get_deserializer()(contents)
+            /// Write the binary contents of this packet to the given buffer
+            pub fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) -> Result<()> {
+                match self {
+                    $(
+                        $(
+                            Packet::$name(p) => p.put_be(buf),
+                        )*
+                    )*
+                    Packet::Unknown { payload, .. } => {
+                        buf.put_slice(&payload[..]);
+                        Ok(())
+                    }
+                }
             }
 
-            /// Write the contents of this packet to the given buffer - should
-            /// not be used unless you really know what you're doing!
-            pub unsafe fn to_bytes(&self, buf: &mut dyn BufMut) -> Result<()> {
-                self.get_type().get_serializer()(self, buf)
+            /// Get the ID used by the game for this packet, or `None` if
+            /// there is no mapping for its type
+            #[cfg(feature = "std")]
+            pub fn game_id(&self, mappings: &Mappings) -> Option<u8> {
+                match self {
+                    Packet::Unknown { id, .. } => Some(*id),
+                    p => mappings.to_game(p.get_type()?),
+                }
+            }
+
+            /// Get the exact number of bytes `to_bytes` would write for this
+            /// packet
+            pub fn encoded_len(&self) -> usize {
+                match self {
+                    $(
+                        $(
+                            Packet::$name(p) => p.encoded_len(),
+                        )*
+                    )*
+                    Packet::Unknown { payload, .. } => payload.len(),
+                }
+            }
+        }
+
+        /// Generates packets of any known type, as well as occasional
+        /// `Packet::Unknown` packets with arbitrary IDs
+        #[cfg(feature = "quickcheck")]
+        impl Arbitrary for Packet {
+            fn arbitrary<G: Gen>(g: &mut G) -> Self {
+                let index = g.next_u32() as usize % (PacketType::NUM_TYPES + 1);
+
+                match PacketType::from_byte(index as u8) {
+                    $(
+                        $(
+                            Some(PacketType::$name) => Packet::$name(Arbitrary::arbitrary(g)),
+                        )*
+                    )*
+                    None => Packet::Unknown {
+                        id: g.next_u32() as u8,
+                        payload: Arbitrary::arbitrary(g),
+                    },
+                }
             }
         }
 
-        type PacketDeserializer = fn(&mut dyn Buf) -> Result<Packet>;
-        type PacketSerializer = fn(&Packet, &mut dyn BufMut) -> Result<()>;
         impl PacketType {
             const VALID_TYPES: [Option<PacketType>; 256] = {
                 let mut arr = [None; 256];
@@ -259,25 +585,14 @@ macro_rules! define_packets {
                 Self::VALID_TYPES[byte as usize]
             }
 
-            /// Get a set of all packet types, as a reference to a static,
-            /// lazily-initialized `HashSet`.
-            pub fn get_all_types() -> &'static HashSet<PacketType> {
-                lazy_static! {
-                    static ref ALL_TYPES: HashSet<PacketType> = {
-                        let mut set = HashSet::with_capacity(256);
-
-                        $(
-                            $(
-                                set.insert(PacketType::$name);
-                            )*
-                        )*
-
-                        set.shrink_to_fit();
-                        set
-                    };
-                }
+            /// All packet types, ordered by their byte representation
+            const ALL_TYPES: &'static [PacketType] = &[
+                $( $( PacketType::$name, )* )*
+            ];
 
-                &ALL_TYPES
+            /// Get all packet types, ordered by their byte representation
+            pub fn get_all_types() -> &'static [PacketType] {
+                Self::ALL_TYPES
             }
 
             /// The number of different packet types
@@ -294,67 +609,35 @@ macro_rules! define_packets {
                 count
             };
 
-            const DESERIALIZERS: [Option<PacketDeserializer>; 256] = {
-                let mut arr: [Option<PacketDeserializer>; 256] = [None; 256];
+            /// Every packet type paired with its name
+            const NAMES: &'static [(PacketType, &'static str)] = &[
+                $( $( (PacketType::$name, stringify!($name)), )* )*
+            ];
 
-                $(
-                    $(
-                        arr[PacketType::$name as usize] = Some(|b| {
-                            $name::get_be(b).map(Packet::$name)
-                        });
-                    )*
-                )*
-
-                arr
-            };
-
-            fn get_deserializer(self) -> PacketDeserializer {
-                Self::DESERIALIZERS[self as usize].unwrap()
+            /// Get every packet type paired with its name, ordered by the byte
+            /// representation of the type
+            pub fn get_name_mappings() -> &'static [(PacketType, &'static str)] {
+                Self::NAMES
             }
 
-            const SERIALIZERS: [Option<PacketSerializer>; 256] = {
-                let mut arr: [Option<PacketSerializer>; 256] = [None; 256];
-
-                $(
-                    $(
-                        arr[PacketType::$name as usize] = Some(|p, b| {
-                            let concrete: &$name = p.downcast_ref().unwrap();
-                            concrete.put_be(b)
-                        });
-                    )*
-                )*
-
-                arr
-            };
-
-            fn get_serializer(self) -> PacketSerializer {
-                Self::SERIALIZERS[self as usize].unwrap()
+            /// Get the name for this packet type
+            pub fn get_name(self) -> &'static str {
+                match self {
+                    $( $( PacketType::$name => stringify!($name), )* )*
+                }
             }
 
-            /// Get a map of packet types to names, as a reference to a static,
-            /// lazily-initialized `HashMap`
-            pub fn get_name_mappings() -> &'static HashMap<PacketType, &'static str> {
-                lazy_static! {
-                    static ref NAMES: HashMap<PacketType, &'static str> = {
-                        let mut map = HashMap::with_capacity(256);
-
+            /// Get the packet type with the given name, as returned by
+            /// `get_name`
+            pub fn from_name(name: &str) -> Option<PacketType> {
+                match name {
+                    $(
                         $(
-                            $(
-                                map.insert(PacketType::$name, stringify!($name));
-                            )*
+                            stringify!($name) => Some(PacketType::$name),
                         )*
-
-                        map.shrink_to_fit();
-                        map
-                    };
+                    )*
+                    _ => None,
                 }
-
-                &NAMES
-            }
-
-            /// Get the name for this packet type
-            pub fn get_name(self) -> &'static str {
-                Self::get_name_mappings()[&self]
             }
 
             const SERVERSIDE: [bool; 256] = {
@@ -378,6 +661,25 @@ macro_rules! define_packets {
             pub fn is_client(self) -> bool {
                 !Self::SERVERSIDE[self as usize]
             }
+
+            /// Get the side of the connection which sends this packet type
+            pub fn side(self) -> Side {
+                if self.is_server() {
+                    Side::Server
+                } else {
+                    Side::Client
+                }
+            }
+
+            /// Get the schema describing the fields of this packet type and
+            /// their binary format
+            pub fn schema(self) -> &'static StructSchema {
+                let wire = match self {
+                    $( $( PacketType::$name => <$name as Schema>::WIRE, )* )*
+                };
+
+                wire.as_struct().expect("packets are always structs")
+            }
         }
 
         /// A trait indicating that a type represents the contents of a packet
@@ -396,14 +698,20 @@ macro_rules! define_packets {
 }
 
 mod unified_definitions {
-    use crate::adapter::{Adapter, Error, Result, RLE};
+    use super::{DecodeMode, DecodeOptions, DirectedPacket, Side};
+    use crate::adapter::{Adapter, Result, RLE};
+    #[cfg(feature = "std")]
+    use crate::mappings::Mappings;
     use crate::packets::data::*;
+    use crate::packets::DecodeError;
+    use crate::prelude::*;
+    use crate::schema::{Schema, StructSchema};
     use bytes::{Buf, BufMut};
-    use lazy_static::lazy_static;
+    use core::convert::{TryFrom, TryInto};
+    use core::result::Result as StdResult;
+    #[cfg(feature = "quickcheck")]
+    use quickcheck::{Arbitrary, Gen};
     use serde::{Deserialize, Serialize};
-    use std::collections::{HashMap, HashSet};
-    use std::convert::{TryFrom, TryInto};
-    use std::result::Result as StdResult;
 
     define_packets! {
         Client {
@@ -436,6 +744,7 @@ mod unified_definitions {
                 rand2: u32,
                 secret: RLE<String>,
                 key_time: u32,
+                #[serde(with = "crate::text::hex_bytes")]
                 key: RLE<Vec<u8>>,
                 map_json: RLE<String, u32>,
                 entry_tag: RLE<String>,
@@ -482,7 +791,7 @@ mod unified_definitions {
             RequestTrade { name: RLE<String> },
             ResetDailyQuests {},
             Reskin { skin_id: u32 },
-            SetCondition { effect: u8, duration: f32 },
+            SetCondition { effect: ConditionEffect, duration: f32 },
             ShootAck { time: u32 },
             SquareHit { time: u32, bullet_id: u8, object_id: u32 },
             Teleport { object_id: u32 },
@@ -513,7 +822,7 @@ mod unified_definitions {
                 armor_pierce: bool
             },
             ArenaDeath { cost: u32 },
-            BuyResult { result: u32, result_string: RLE<String> }, // TODO: consts for this?
+            BuyResult { result: BuyResultCode, result_string: RLE<String> },
             ClientStat { name: RLE<String>, value: u32 },
             CreateSuccess { object_id: u32, char_id: u32 },
             Damage {
@@ -533,7 +842,7 @@ mod unified_definitions {
                 zombie_id: u32,
             },
             DeletePet { pet_id: u32 },
-            EnemyShoot {
+            EnemyShoot (ManualArbitrary) {
                 bullet_id: u8,
                 owner_id: u32,
                 bullet_type: u8,
@@ -544,13 +853,13 @@ mod unified_definitions {
                 angle_inc: Option<f32>
             },
             EvolvePet { pet_id: u32, initial_skin: u32, final_skin: u32 },
-            Failure { error_id: u32, error_description: RLE<String> }, // TODO: consts?
+            Failure { error_id: FailureCode, error_description: RLE<String> },
             File { filename: RLE<String>, file: RLE<String, u32> }, // TODO: investigate this
-            GlobalNotification { notification_type: u32, text: RLE<String> },
+            GlobalNotification { notification_type: NotificationType, text: RLE<String> },
             Goto { object_id: u32, pos: WorldPosData },
             GuildResult { success: bool, line_builder_json: RLE<String> },
             HatchPet { pet_name: RLE<String>, pet_skin: u32, item_type: u32 },
-            InvResult { result: u32 },
+            InvResult { result: InvResultCode },
             InvitedToGuild { name: RLE<String>, guild_name: RLE<String> },
             ImminentArenaWave { current_runtime: u32 },
             KeyInfoResponse { name: RLE<String>, description: RLE<String>, creator: RLE<String> },
@@ -574,7 +883,13 @@ mod unified_definitions {
             Notification { object_id: u32, message: RLE<String>, color: u32 },
             PasswordPrompt { clean_password_status: u32 },
             PetYardUpdate { typ: u32 },
-            Pic(ManualAdapter) { w: u32, h: u32, bitmap_data: Vec<u8> },
+            Pic (ManualArbitrary) {
+                w: u32,
+                h: u32,
+                #[adapter(count = (w as usize).saturating_mul(h as usize).saturating_mul(4))]
+                #[serde(with = "crate::text::hex_bytes")]
+                bitmap_data: Vec<u8>
+            },
             Ping { serial: u32 },
             PlaySound { owner_id: u32, sound_id: u8 },
             QuestObjId { object_id: u32 },
@@ -589,6 +904,7 @@ mod unified_definitions {
                 game_id: u32,
                 key_time: u32,
                 is_from_arena: bool,
+                #[serde(with = "crate::text::hex_bytes")]
                 key: RLE<Vec<u8>>
             },
             ReskinUnlock { skin_id: u32, is_pet_skin: u32 },
@@ -600,8 +916,8 @@ mod unified_definitions {
                 angle: f32,
                 damage: u16
             },
-            ShowEffect { // TODO: consts?
-                effect_type: u8,
+            ShowEffect {
+                effect_type: EffectType,
                 target_object_id: u32,
                 pos1: WorldPosData,
                 pos2: WorldPosData,
@@ -620,7 +936,7 @@ mod unified_definitions {
             },
             TradeAccepted { my_offer: RLE<Vec<bool>>, your_offer: RLE<Vec<bool>> },
             TradeChanged { offer: RLE<Vec<bool>> },
-            TradeDone { code: u32, description: RLE<String> }, // TODO: consts?
+            TradeDone { code: TradeResultCode, description: RLE<String> },
             TradeRequested { name: RLE<String> },
             TradeStart {
                 my_items: RLE<Vec<TradeItem>>,
@@ -635,33 +951,85 @@ mod unified_definitions {
             VerifyEmail {}
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client::Pong;
+    use super::*;
+    use crate::adapter::RLE;
+    use crate::packets::data::{ObjectStatusData, StatData, StatType, WorldPosData};
+    use crate::packets::DecodeError;
+    use crate::prelude::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn test_from_bytes() {
+        let packet = Packet::Pong(Pong { serial: 1, time: 2 });
+        let mut buf = vec![];
+        packet.to_bytes(&mut buf).expect("encoding error");
+        assert_eq!(packet.encoded_len(), buf.len());
+
+        assert_eq!(
+            Packet::from_bytes(PacketType::Pong, Side::Client, &buf).expect("decoding error"),
+            packet
+        );
+
+        assert_matches!(
+            Packet::from_bytes(PacketType::Pong, Side::Server, &buf),
+            Err(DecodeError::WrongSide { .. })
+        );
+
+        buf.push(0xab);
+        assert_matches!(
+            Packet::from_bytes(PacketType::Pong, Side::Client, &buf),
+            Err(DecodeError::TrailingBytes { remaining: 1, ref hex, .. }) if hex == "ab"
+        );
+        assert_eq!(
+            Packet::decode(PacketType::Pong, Side::Client, &buf, DecodeMode::Lenient)
+                .expect("decoding error"),
+            (packet, &[0xab][..])
+        );
+
+        assert_matches!(
+            Packet::from_bytes(PacketType::Pong, Side::Client, &buf[..4]),
+            Err(DecodeError::AdapterError(_))
+        );
+    }
 
-    // manually implemented adapter for Pic packet
-    impl Adapter for Pic {
-        fn get_be(bytes: &mut dyn Buf) -> Result<Self> {
-            let w = u32::get_be(bytes)?;
-            let h = u32::get_be(bytes)?;
-
-            let reqd_bytes = (w as usize) * (h as usize) * 4;
-
-            if bytes.remaining() >= reqd_bytes {
-                let mut bitmap_data = vec![0u8; reqd_bytes];
-                bytes.copy_to_slice(&mut bitmap_data[..]);
-                Ok(Self { w, h, bitmap_data })
-            } else {
-                Err(Error::InsufficientBytes {
-                    remaining: bytes.remaining(),
-                    needed: reqd_bytes,
-                })
+    #[test]
+    fn test_error_path() {
+        let status = ObjectStatusData {
+            object_id: 1,
+            pos: WorldPosData { x: 0.0, y: 0.0 },
+            stats: RLE::new(vec![
+                StatData::Integer(StatType::HP_STAT, 100),
+                StatData::String(StatType::NAME_STAT, "name".to_owned()),
+            ]),
+        };
+        let packet = Packet::NewTick(server::NewTick {
+            tick_id: 1,
+            tick_time: 2,
+            statuses: RLE::new(vec![status.clone(), status]),
+        });
+
+        let mut buf = vec![];
+        packet.to_bytes(&mut buf).expect("encoding error");
+
+        // cut off the last byte of the name of the second status
+        match Packet::from_bytes(PacketType::NewTick, Side::Server, &buf[..buf.len() - 1]) {
+            Err(DecodeError::AdapterError(e)) => {
+                assert_eq!(e.path(), Some("NewTick.statuses[1].stats[1].value"));
+                assert_eq!(
+                    e.to_string(),
+                    format!(
+                        "Error decoding NewTick.statuses[1].stats[1].value at offset {}: \
+                         Not enough bytes remaining in buffer: need 4 bytes, 3 bytes remaining",
+                        buf.len() - 4
+                    )
+                );
             }
-        }
-
-        fn put_be(&self, bytes: &mut dyn BufMut) -> Result<()> {
-            self.w.put_be(bytes)?;
-            self.h.put_be(bytes)?;
-            bytes.put_slice(&self.bitmap_data[..]);
-            Ok(())
+            result => panic!("unexpected result: {:?}", result),
         }
     }
-
 }
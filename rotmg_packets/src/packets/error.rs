@@ -0,0 +1,72 @@
+//! Errors which may occur when decoding packets
+
+use super::{PacketType, Side};
+use crate::adapter::Error as AdapterError;
+use crate::prelude::*;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// An error decoding a packet from its binary contents
+#[derive(Debug)]
+pub enum DecodeError {
+    /// Packets of this type are not sent by the given side of the connection
+    WrongSide {
+        /// The type of the packet
+        packet_type: PacketType,
+        /// The side which supposedly sent the packet
+        sender: Side,
+    },
+
+    /// The packet was decoded, but not all of the contents were used
+    TrailingBytes {
+        /// The type of the packet
+        packet_type: PacketType,
+        /// The number of bytes remaining in the buffer
+        remaining: usize,
+        /// The remaining bytes, encoded as hexadecimal
+        hex: String,
+    },
+
+    /// An error raised by the `Adapter` implementation for the packet
+    AdapterError(AdapterError),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            DecodeError::WrongSide {
+                packet_type,
+                sender,
+            } => write!(
+                f,
+                "{:?} packets are not sent by the {:?}",
+                packet_type, sender
+            ),
+            DecodeError::TrailingBytes {
+                packet_type,
+                remaining,
+                hex,
+            } => write!(
+                f,
+                "{} bytes remaining after decoding {:?} packet: {}",
+                remaining, packet_type, hex
+            ),
+            DecodeError::AdapterError(e) => write!(f, "Adapter error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::AdapterError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AdapterError> for DecodeError {
+    fn from(e: AdapterError) -> Self {
+        DecodeError::AdapterError(e)
+    }
+}
@@ -2,7 +2,12 @@
 //! networking
 
 pub mod data;
+pub mod view;
 
+#[cfg(feature = "quickcheck")]
+mod arbitrary;
 mod definitions;
+mod error;
 
 pub use self::definitions::*;
+pub use self::error::*;
@@ -0,0 +1,647 @@
+//! Borrowed, lazily decoded views of large packets.
+//!
+//! Decoding a `NewTick` or `Update` packet normally allocates a `Vec` for
+//! every list of objects and stats, and a `String` for every string stat. The
+//! views in this module instead borrow the binary contents of the packet, and
+//! only decode the parts which are actually accessed, without allocating.
+//!
+//! The whole packet is validated when a view is created, so iterating over
+//! the lists in a view never fails. The decode options used to create a view
+//! are kept with its lists, so their items are always read the same way as
+//! when they were validated. Views can be converted to the equivalent owned
+//! types with `to_owned` when needed.
+//!
+//! # Examples
+//!
+//! ```
+//! # use rotmg_packets::adapter::{Adapter, RLE};
+//! # use rotmg_packets::packets::data::{ObjectStatusData, StatData, StatType, WorldPosData};
+//! # use rotmg_packets::packets::server::NewTick;
+//! # use rotmg_packets::packets::view::{NewTickView, View};
+//! let tick = NewTick {
+//!     tick_id: 1,
+//!     tick_time: 200,
+//!     statuses: RLE::new(vec![ObjectStatusData {
+//!         object_id: 7,
+//!         pos: WorldPosData { x: 1.0, y: 2.0 },
+//!         stats: RLE::new(vec![StatData::Integer(StatType::HP_STAT, 100)]),
+//!     }]),
+//! };
+//!
+//! let mut contents = vec![];
+//! tick.put_be(&mut contents).unwrap();
+//!
+//! let view = NewTickView::from_bytes(&contents).unwrap();
+//! let status = view.statuses().iter().next().unwrap();
+//! assert_eq!(status.object_id(), 7);
+//! assert_eq!(status.stat(StatType::HP_STAT).unwrap().as_integer(), Some(100));
+//! assert_eq!(view.to_owned(), tick);
+//! ```
+
+use super::data::*;
+use super::server::{NewTick, Update};
+use super::{DecodeOptions, PacketType};
+use crate::adapter::{Error, Result, RLE};
+use crate::prelude::*;
+use core::convert::TryInto;
+use core::fmt::{Debug, Formatter, Result as FmtResult};
+use core::marker::PhantomData;
+use core::str;
+
+/// A value which can be read from a borrowed buffer without allocating
+pub trait View<'a>: Sized {
+    /// Read a value from the start of the given buffer, advancing it past the
+    /// value
+    fn read(bytes: &mut &'a [u8]) -> Result<Self> {
+        Self::read_with(bytes, &DecodeOptions::default())
+    }
+
+    /// Read a value from the start of the given buffer with the given decode
+    /// options, advancing it past the value
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self>;
+
+    /// Read a value which takes up the entire buffer
+    fn from_bytes(contents: &'a [u8]) -> Result<Self> {
+        Self::from_bytes_with(contents, &DecodeOptions::default())
+    }
+
+    /// Read a value which takes up the entire buffer with the given decode
+    /// options
+    fn from_bytes_with(contents: &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        let mut bytes = contents;
+        let value = Self::read_with(&mut bytes, options)?;
+
+        if bytes.is_empty() {
+            Ok(value)
+        } else {
+            Err(Error::InvalidData(format!(
+                "{} bytes remaining after reading view",
+                bytes.len()
+            )))
+        }
+    }
+}
+
+/// A view of the contents of a packet
+pub trait PacketView<'a>: View<'a> {
+    /// The type of packet this view reads
+    const PACKET_TYPE: PacketType;
+}
+
+/// Take the given number of bytes from the start of the buffer
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if bytes.len() < len {
+        return Err(Error::InsufficientBytes {
+            remaining: bytes.len(),
+            needed: len,
+        });
+    }
+
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+/// Read a field of a view, adding its name to the path of any error
+fn field<'a, T: View<'a>>(bytes: &mut &'a [u8], options: &DecodeOptions, name: &str) -> Result<T> {
+    T::read_with(bytes, options).map_err(|e| e.in_field(name, bytes.len()))
+}
+
+// numbers are read directly from the buffer
+macro_rules! number_view {
+    ($( $type:ty ),* $(,)?) => {
+        $(
+            impl<'a> View<'a> for $type {
+                fn read_with(bytes: &mut &'a [u8], _options: &DecodeOptions) -> Result<Self> {
+                    let raw = take(bytes, core::mem::size_of::<$type>())?;
+                    Ok(<$type>::from_be_bytes(raw.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+number_view! { u8, u16, u32, i32, f32 }
+
+impl<'a> View<'a> for bool {
+    fn read_with(bytes: &mut &'a [u8], _options: &DecodeOptions) -> Result<Self> {
+        u8::read(bytes).map(|b| b != 0)
+    }
+}
+
+/// Strings prefixed with their length as a `u16`
+impl<'a> View<'a> for &'a str {
+    fn read_with(bytes: &mut &'a [u8], _options: &DecodeOptions) -> Result<Self> {
+        let len = u16::read(bytes)?;
+        let raw = take(bytes, len.into())?;
+        str::from_utf8(raw).map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+impl<'a> View<'a> for WorldPosData {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        Ok(WorldPosData {
+            x: field(bytes, options, "x")?,
+            y: field(bytes, options, "y")?,
+        })
+    }
+}
+
+impl<'a> View<'a> for GroundTileData {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        Ok(GroundTileData {
+            x: field(bytes, options, "x")?,
+            y: field(bytes, options, "y")?,
+            tile: field(bytes, options, "tile")?,
+        })
+    }
+}
+
+/// A list of values prefixed with its length as a `u16`, decoded as it is
+/// iterated over
+pub struct ListView<'a, T> {
+    len: usize,
+    items: &'a [u8],
+    options: DecodeOptions,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: View<'a>> ListView<'a, T> {
+    /// Get the number of items in this list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether this list is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the items in this list, decoding each one as needed
+    pub fn iter(&self) -> ListIter<'a, T> {
+        ListIter {
+            remaining: self.len,
+            items: self.items,
+            options: self.options,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Every item is read once to validate the list and find where it ends, and
+/// the options are kept to read the items the same way when iterating
+impl<'a, T: View<'a>> View<'a> for ListView<'a, T> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        let len = usize::from(u16::read(bytes)?);
+        let start = *bytes;
+
+        for i in 0..len {
+            T::read_with(bytes, options).map_err(|e| e.at_index(i, bytes.len()))?;
+        }
+
+        Ok(ListView {
+            len,
+            items: &start[..start.len() - bytes.len()],
+            options: *options,
+            marker: PhantomData,
+        })
+    }
+}
+
+impl<'a, T> Clone for ListView<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for ListView<'a, T> {}
+
+impl<'a, T: View<'a> + Debug> Debug for ListView<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: View<'a>> IntoIterator for ListView<'a, T> {
+    type Item = T;
+    type IntoIter = ListIter<'a, T>;
+
+    fn into_iter(self) -> ListIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the items of a `ListView`
+pub struct ListIter<'a, T> {
+    remaining: usize,
+    items: &'a [u8],
+    options: DecodeOptions,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: View<'a>> Iterator for ListIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        // the items were validated with the same options, so this only fails
+        // if a view's `read_with` isn't deterministic, in which case the
+        // rest of the list can't be found
+        match T::read_with(&mut self.items, &self.options) {
+            Ok(item) => {
+                self.remaining -= 1;
+                Some(item)
+            }
+            Err(_) => {
+                self.remaining = 0;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: View<'a>> ExactSizeIterator for ListIter<'a, T> {}
+
+/// The value of a stat, borrowed from the packet contents
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StatValueView<'a> {
+    /// A string value
+    String(&'a str),
+    /// An integer value
+    Integer(i32),
+}
+
+/// A view of a `StatData`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StatView<'a> {
+    id: u8,
+    value: StatValueView<'a>,
+}
+
+impl<'a> StatView<'a> {
+    /// Get the byte representing the type of this stat
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Get the type of this stat, or `None` if the type is unknown
+    pub fn stat_type(&self) -> Option<StatType> {
+        StatType::from_byte(self.id)
+    }
+
+    /// Get the value of this stat
+    pub fn value(&self) -> StatValueView<'a> {
+        self.value
+    }
+
+    /// Get the value of this stat if it is an integer
+    pub fn as_integer(&self) -> Option<i32> {
+        match self.value {
+            StatValueView::Integer(int) => Some(int),
+            StatValueView::String(_) => None,
+        }
+    }
+
+    /// Get the value of this stat if it is a string
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.value {
+            StatValueView::String(str) => Some(str),
+            StatValueView::Integer(_) => None,
+        }
+    }
+
+    /// Convert this view to an owned `StatData`
+    pub fn to_owned(&self) -> StatData {
+        match (self.stat_type(), self.value) {
+            (Some(typ), StatValueView::String(str)) => StatData::String(typ, str.to_owned()),
+            (Some(typ), StatValueView::Integer(int)) => StatData::Integer(typ, int),
+            (None, StatValueView::String(str)) => {
+                StatData::Unknown(self.id, StatValue::String(str.to_owned()))
+            }
+            (None, StatValueView::Integer(int)) => {
+                StatData::Unknown(self.id, StatValue::Integer(int))
+            }
+        }
+    }
+}
+
+/// Stats are read like `StatData`, including the handling of unknown stats
+impl<'a> View<'a> for StatView<'a> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        let id = u8::read(bytes)?;
+
+        let value = if options.unknown_stats.is_string(id) {
+            field(bytes, options, "value").map(StatValueView::String)?
+        } else {
+            field(bytes, options, "value").map(StatValueView::Integer)?
+        };
+
+        Ok(StatView { id, value })
+    }
+}
+
+/// A view of an `ObjectStatusData`
+#[derive(Debug, Clone)]
+pub struct ObjectStatusView<'a> {
+    object_id: u32,
+    pos: WorldPosData,
+    stats: ListView<'a, StatView<'a>>,
+}
+
+impl<'a> ObjectStatusView<'a> {
+    /// Get the ID of the object
+    pub fn object_id(&self) -> u32 {
+        self.object_id
+    }
+
+    /// Get the position of the object
+    pub fn pos(&self) -> &WorldPosData {
+        &self.pos
+    }
+
+    /// Get the stats of the object
+    pub fn stats(&self) -> ListView<'a, StatView<'a>> {
+        self.stats
+    }
+
+    /// Find the first stat of the given type
+    pub fn stat(&self, typ: StatType) -> Option<StatView<'a>> {
+        self.stats.iter().find(|s| s.id == typ.to_byte())
+    }
+
+    /// Convert this view to an owned `ObjectStatusData`
+    pub fn to_owned(&self) -> ObjectStatusData {
+        ObjectStatusData {
+            object_id: self.object_id,
+            pos: self.pos.clone(),
+            stats: RLE::new(self.stats.iter().map(|s| s.to_owned()).collect()),
+        }
+    }
+}
+
+impl<'a> View<'a> for ObjectStatusView<'a> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        Ok(ObjectStatusView {
+            object_id: field(bytes, options, "object_id")?,
+            pos: field(bytes, options, "pos")?,
+            stats: field(bytes, options, "stats")?,
+        })
+    }
+}
+
+/// A view of an `ObjectData`
+#[derive(Debug, Clone)]
+pub struct ObjectView<'a> {
+    object_type: u16,
+    status: ObjectStatusView<'a>,
+}
+
+impl<'a> ObjectView<'a> {
+    /// Get the type of the object
+    pub fn object_type(&self) -> u16 {
+        self.object_type
+    }
+
+    /// Get the status of the object
+    pub fn status(&self) -> &ObjectStatusView<'a> {
+        &self.status
+    }
+
+    /// Convert this view to an owned `ObjectData`
+    pub fn to_owned(&self) -> ObjectData {
+        ObjectData {
+            object_type: self.object_type,
+            status: self.status.to_owned(),
+        }
+    }
+}
+
+impl<'a> View<'a> for ObjectView<'a> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        Ok(ObjectView {
+            object_type: field(bytes, options, "object_type")?,
+            status: field(bytes, options, "status")?,
+        })
+    }
+}
+
+/// A view of a `NewTick` packet
+#[derive(Debug, Clone, Copy)]
+pub struct NewTickView<'a> {
+    tick_id: u32,
+    tick_time: u32,
+    statuses: ListView<'a, ObjectStatusView<'a>>,
+}
+
+impl<'a> NewTickView<'a> {
+    /// Get the ID of the tick
+    pub fn tick_id(&self) -> u32 {
+        self.tick_id
+    }
+
+    /// Get the time of the tick
+    pub fn tick_time(&self) -> u32 {
+        self.tick_time
+    }
+
+    /// Get the updated statuses of objects
+    pub fn statuses(&self) -> ListView<'a, ObjectStatusView<'a>> {
+        self.statuses
+    }
+
+    /// Convert this view to an owned `NewTick` packet
+    pub fn to_owned(&self) -> NewTick {
+        NewTick {
+            tick_id: self.tick_id,
+            tick_time: self.tick_time,
+            statuses: RLE::new(self.statuses.iter().map(|s| s.to_owned()).collect()),
+        }
+    }
+}
+
+impl<'a> View<'a> for NewTickView<'a> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        let view = NewTickView {
+            tick_id: field(bytes, options, "tick_id")?,
+            tick_time: field(bytes, options, "tick_time")?,
+            statuses: field(bytes, options, "statuses")?,
+        };
+
+        Ok(view)
+    }
+}
+
+impl<'a> PacketView<'a> for NewTickView<'a> {
+    const PACKET_TYPE: PacketType = PacketType::NewTick;
+}
+
+/// A view of an `Update` packet
+#[derive(Debug, Clone, Copy)]
+pub struct UpdateView<'a> {
+    tiles: ListView<'a, GroundTileData>,
+    new_objs: ListView<'a, ObjectView<'a>>,
+    drops: ListView<'a, u32>,
+}
+
+impl<'a> UpdateView<'a> {
+    /// Get the updated tiles
+    pub fn tiles(&self) -> ListView<'a, GroundTileData> {
+        self.tiles
+    }
+
+    /// Get the objects which came into view
+    pub fn new_objs(&self) -> ListView<'a, ObjectView<'a>> {
+        self.new_objs
+    }
+
+    /// Get the IDs of the objects which went out of view
+    pub fn drops(&self) -> ListView<'a, u32> {
+        self.drops
+    }
+
+    /// Convert this view to an owned `Update` packet
+    pub fn to_owned(&self) -> Update {
+        Update {
+            tiles: RLE::new(self.tiles.iter().collect()),
+            new_objs: RLE::new(self.new_objs.iter().map(|o| o.to_owned()).collect()),
+            drops: RLE::new(self.drops.iter().collect()),
+        }
+    }
+}
+
+impl<'a> View<'a> for UpdateView<'a> {
+    fn read_with(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<Self> {
+        Ok(UpdateView {
+            tiles: field(bytes, options, "tiles")?,
+            new_objs: field(bytes, options, "new_objs")?,
+            drops: field(bytes, options, "drops")?,
+        })
+    }
+}
+
+impl<'a> PacketView<'a> for UpdateView<'a> {
+    const PACKET_TYPE: PacketType = PacketType::Update;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::Adapter;
+    use crate::packets::DecodeMode;
+
+    fn status(id: u32) -> ObjectStatusData {
+        ObjectStatusData {
+            object_id: id,
+            pos: WorldPosData { x: 1.5, y: -2.0 },
+            stats: RLE::new(vec![
+                StatData::Integer(StatType::HP_STAT, 100),
+                StatData::String(StatType::NAME_STAT, "name".to_owned()),
+                StatData::Unknown(250, StatValue::Integer(-1)),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_update_view() {
+        let update = Update {
+            tiles: RLE::new(vec![GroundTileData {
+                x: 1,
+                y: 2,
+                tile: 3,
+            }]),
+            new_objs: RLE::new(vec![ObjectData {
+                object_type: 0x0300,
+                status: status(5),
+            }]),
+            drops: RLE::new(vec![6, 7]),
+        };
+
+        let mut buf = vec![];
+        update.put_be(&mut buf).unwrap();
+
+        let view = UpdateView::from_bytes(&buf).unwrap();
+        assert_eq!(view.tiles().len(), 1);
+        assert_eq!(view.drops().iter().collect::<Vec<_>>(), vec![6, 7]);
+
+        let obj = view.new_objs().iter().next().unwrap();
+        assert_eq!(obj.object_type(), 0x0300);
+        assert_eq!(obj.status().object_id(), 5);
+
+        let name = obj.status().stat(StatType::NAME_STAT).unwrap();
+        assert_eq!(name.as_str(), Some("name"));
+        assert_eq!(view.to_owned(), update);
+
+        // views are validated up front, reporting the same errors as decoding
+        let error = UpdateView::from_bytes(&buf[..buf.len() - 1]).unwrap_err();
+        let expected = Update::get_be(&mut &buf[..buf.len() - 1]).unwrap_err();
+        assert_eq!(error.path(), Some("drops[1]"));
+        assert_eq!(error.path(), expected.path());
+
+        buf.push(0);
+        assert!(UpdateView::from_bytes(&buf).is_err());
+    }
+
+    #[test]
+    fn test_new_tick_view() {
+        let tick = NewTick {
+            tick_id: 1,
+            tick_time: 2,
+            statuses: RLE::new((0..10).map(status).collect()),
+        };
+
+        let mut buf = vec![];
+        tick.put_be(&mut buf).unwrap();
+
+        let view = NewTickView::from_bytes(&buf).unwrap();
+        assert_eq!(view.tick_id(), 1);
+        assert_eq!(view.tick_time(), 2);
+
+        let statuses = view.statuses();
+        assert_eq!(statuses.iter().len(), 10);
+        assert_eq!(
+            statuses.iter().map(|s| s.object_id()).sum::<u32>(),
+            (0..10).sum::<u32>()
+        );
+        assert_eq!(view.to_owned(), tick);
+
+        let error = NewTickView::from_bytes(&buf[..buf.len() - 1]).unwrap_err();
+        assert_eq!(error.path(), Some("statuses[9].stats[2].value"));
+    }
+
+    #[test]
+    fn test_view_options() {
+        let status = ObjectStatusData {
+            object_id: 1,
+            pos: WorldPosData { x: 0.0, y: 0.0 },
+            stats: RLE::new(vec![
+                StatData::Unknown(250, StatValue::String("unknown".to_owned())),
+                StatData::Integer(StatType::HP_STAT, 100),
+            ]),
+        };
+
+        let mut buf = vec![];
+        status.put_be(&mut buf).unwrap();
+
+        // the unknown stat is only read correctly when guessed to be a string
+        assert!(ObjectStatusView::from_bytes(&buf).is_err());
+        let options = DecodeOptions {
+            mode: DecodeMode::Strict,
+            unknown_stats: UnknownStatGuess::String,
+        };
+        let view = ObjectStatusView::from_bytes_with(&buf, &options).unwrap();
+
+        // and iterating over the stats later reads them in the same way
+        let stats = view.stats().iter().collect::<Vec<_>>();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].as_str(), Some("unknown"));
+        assert_eq!(stats[1].as_integer(), Some(100));
+        assert_eq!(view.to_owned(), status);
+    }
+}
@@ -0,0 +1,407 @@
+//! Static descriptions of the binary format of packets and their fields, for
+//! building generic tools such as inspectors, dissectors and code generators.
+//!
+//! Every type which can be encoded implements `Schema`, which describes its
+//! wire format as a `WireType`. Implementations for structs can be generated
+//! with `#[derive(Schema)]`, which understands the same `adapter` attributes
+//! as `#[derive(Adapter)]`. The schema of each packet type can be retrieved
+//! with `PacketType::schema`.
+//!
+//! # Examples
+//!
+//! ```
+//! # use rotmg_packets::packets::PacketType;
+//! # use rotmg_packets::schema::{Length, WireType};
+//! let schema = PacketType::Failure.schema();
+//! assert_eq!(schema.name, "Failure");
+//!
+//! let field = &schema.fields[1];
+//! assert_eq!(field.name, "error_description");
+//! assert_eq!(field.rust_type, "RLE<String>");
+//! assert_eq!(field.wire, WireType::String(Length::Prefixed(&WireType::U16)));
+//! ```
+
+use crate::adapter::{Adapter, CompressedInt, Result, RLE};
+use crate::prelude::*;
+use bytes::Buf;
+use core::fmt::Debug;
+use serde::Serialize;
+
+pub use rotmg_packets_derive::Schema;
+
+/// A type with a known binary format
+pub trait Schema {
+    /// The wire format of this type
+    const WIRE: WireType;
+}
+
+/// A sequence type (`Vec<T>` or `String`) with a known binary format for its
+/// items
+pub trait SequenceSchema {
+    /// The wire format of each item, or `None` for UTF-8 strings
+    const ITEM: Option<&'static WireType>;
+}
+
+/// The binary format of a value
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum WireType {
+    /// A single byte, 0 for `false` and anything else for `true`
+    Bool,
+    /// An unsigned 8-bit integer
+    U8,
+    /// An unsigned 16-bit big endian integer
+    U16,
+    /// An unsigned 32-bit big endian integer
+    U32,
+    /// An unsigned 64-bit big endian integer
+    U64,
+    /// An unsigned 128-bit big endian integer
+    U128,
+    /// A signed 8-bit integer
+    I8,
+    /// A signed 16-bit big endian integer
+    I16,
+    /// A signed 32-bit big endian integer
+    I32,
+    /// A signed 64-bit big endian integer
+    I64,
+    /// A signed 128-bit big endian integer
+    I128,
+    /// A 32-bit big endian floating point number
+    F32,
+    /// A 64-bit big endian floating point number
+    F64,
+    /// A variable-length signed integer, see `CompressedInt`
+    CompressedInt,
+    /// A UTF-8 string with the given length in bytes
+    String(Length),
+    /// A sequence of items of the given type
+    List(Length, &'static WireType),
+    /// Values of the given types, one after another
+    Tuple(&'static [WireType]),
+    /// A value which is only present when bytes remain
+    Optional(&'static WireType),
+    /// A struct with the given fields
+    Struct(&'static StructSchema),
+    /// A format which can't be described statically
+    Custom(&'static CustomSchema),
+}
+
+/// The way the length of a sequence is determined
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum Length {
+    /// The sequence is prefixed with its length, encoded as the given integer
+    /// type
+    Prefixed(&'static WireType),
+    /// The length is given by an expression, which may refer to previously
+    /// decoded fields by name
+    Counted(&'static CountSchema),
+    /// The sequence always has the given length
+    Fixed(usize),
+}
+
+/// The binary format of a struct
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct StructSchema {
+    /// The name of the struct
+    pub name: &'static str,
+    /// The fields of the struct, in the order they are encoded
+    pub fields: &'static [FieldSchema],
+}
+
+/// The binary format of a single field of a struct
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct FieldSchema {
+    /// The name of the field, or its index for tuple structs
+    pub name: &'static str,
+    /// The Rust type of the field, as written in the struct definition
+    pub rust_type: &'static str,
+    /// The wire format of the field
+    pub wire: WireType,
+    /// When the field is present in the binary format
+    pub presence: Presence,
+}
+
+/// A binary format which can't be described statically, such as one where
+/// the type of a value depends on a previously decoded value
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CustomSchema {
+    /// A short description of the format
+    pub description: &'static str,
+    /// Decode a value in this format, returning it formatted with `Debug`
+    #[serde(skip)]
+    pub decode: fn(&mut dyn Buf) -> Result<String>,
+}
+
+/// A function looking up the value of an integer field by name
+pub type FieldLookup<'a> = &'a dyn Fn(&str) -> Option<i128>;
+
+/// An expression giving the length of a sequence, as written in a `count`
+/// attribute, along with the code generated to evaluate it
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CountSchema {
+    /// The expression, as written in the `count` attribute
+    pub expr: &'static str,
+    /// Evaluate the expression exactly as the derived adapter does, looking up
+    /// the values of the fields it refers to by name. `None` is returned if a
+    /// field is missing or out of range for its type, or if the result is out
+    /// of range for a `usize`.
+    #[serde(skip)]
+    pub eval: fn(FieldLookup) -> Option<usize>,
+}
+
+/// Count schemas are compared by their expressions
+impl PartialEq for CountSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+/// When a field is present in the binary format
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum Presence {
+    /// The field is always encoded
+    Always,
+    /// The field is only decoded when bytes remain, and is otherwise given a
+    /// default value
+    Trailing,
+    /// The field is never encoded
+    Skipped,
+}
+
+impl WireType {
+    /// The wire format of the sequence type `T` with the given length
+    pub const fn sequence<T: SequenceSchema>(length: Length) -> WireType {
+        match T::ITEM {
+            Some(item) => WireType::List(length, item),
+            None => WireType::String(length),
+        }
+    }
+
+    /// Get the fixed number of bytes a value of this type is encoded as, or
+    /// `None` if the length depends on the value
+    pub fn fixed_len(&self) -> Option<usize> {
+        match self {
+            WireType::Bool | WireType::U8 | WireType::I8 => Some(1),
+            WireType::U16 | WireType::I16 => Some(2),
+            WireType::U32 | WireType::I32 | WireType::F32 => Some(4),
+            WireType::U64 | WireType::I64 | WireType::F64 => Some(8),
+            WireType::U128 | WireType::I128 => Some(16),
+            WireType::List(Length::Fixed(count), item) => Some(count * item.fixed_len()?),
+            WireType::Tuple(items) => items.iter().map(WireType::fixed_len).sum(),
+            WireType::Struct(schema) => schema
+                .fields
+                .iter()
+                .map(|f| match f.presence {
+                    Presence::Always => f.wire.fixed_len(),
+                    Presence::Trailing => None,
+                    Presence::Skipped => Some(0),
+                })
+                .sum(),
+            _ => None,
+        }
+    }
+
+    /// Get the schema of this type if it is a struct
+    pub fn as_struct(&self) -> Option<&'static StructSchema> {
+        match self {
+            WireType::Struct(schema) => Some(schema),
+            _ => None,
+        }
+    }
+}
+
+impl CustomSchema {
+    /// Describe the format of `T`, decoding values using its `Adapter`
+    pub const fn new<T: Adapter + Debug>(description: &'static str) -> Self {
+        Self {
+            description,
+            decode: decode_debug::<T>,
+        }
+    }
+}
+
+/// Custom schemas are compared by their descriptions
+impl PartialEq for CustomSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.description == other.description
+    }
+}
+
+/// Decode a value of type `T`, formatting it with `Debug`
+fn decode_debug<T: Adapter + Debug>(bytes: &mut dyn Buf) -> Result<String> {
+    T::get_be(bytes).map(|value| format!("{:?}", value))
+}
+
+impl StructSchema {
+    /// Get the schema of the field with the given name
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+// the schemas of primitive types
+macro_rules! primitive_schema {
+    ($( $type:ty => $wire:ident ),* $(,)?) => {
+        $(
+            impl Schema for $type {
+                const WIRE: WireType = WireType::$wire;
+            }
+        )*
+    };
+}
+
+primitive_schema! {
+    bool => Bool,
+    u8 => U8, u16 => U16, u32 => U32, u64 => U64, u128 => U128,
+    i8 => I8, i16 => I16, i32 => I32, i64 => I64, i128 => I128,
+    f32 => F32, f64 => F64,
+    CompressedInt => CompressedInt,
+}
+
+impl<T: Schema> Schema for Option<T> {
+    const WIRE: WireType = WireType::Optional(&T::WIRE);
+}
+
+impl<T: Schema, const N: usize> Schema for [T; N] {
+    const WIRE: WireType = WireType::List(Length::Fixed(N), &T::WIRE);
+}
+
+impl<T: SequenceSchema, S: Schema> Schema for RLE<T, S> {
+    const WIRE: WireType = WireType::sequence::<T>(Length::Prefixed(&S::WIRE));
+}
+
+impl<T: Schema> SequenceSchema for Vec<T> {
+    const ITEM: Option<&'static WireType> = Some(&T::WIRE);
+}
+
+impl SequenceSchema for String {
+    const ITEM: Option<&'static WireType> = None;
+}
+
+// tuples are described by each of their elements in order
+macro_rules! tuple_schema {
+    ($( ( $( $name:ident ),* ) ),* $(,)?) => {
+        $(
+            impl<$( $name: Schema ),*> Schema for ($( $name, )*) {
+                const WIRE: WireType = WireType::Tuple(&[$( $name::WIRE ),*]);
+            }
+        )*
+    };
+}
+
+tuple_schema! {
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::{Adapter, CompressedRLE};
+    use crate::packets::data::{StatData, WorldPosData};
+    use crate::packets::PacketType;
+
+    #[derive(Schema)]
+    #[allow(dead_code)]
+    struct Derived {
+        count: u8,
+        #[adapter(count = count)]
+        items: Vec<u16>,
+        #[adapter(len = u32)]
+        name: String,
+        fixed: [u8; 3],
+        #[adapter(skip)]
+        skipped: u32,
+        #[adapter(trailing)]
+        extra: Option<CompressedRLE<Vec<bool>>>,
+    }
+
+    #[test]
+    fn test_derived_schema() {
+        let schema = Derived::WIRE.as_struct().unwrap();
+        assert_eq!(schema.name, "Derived");
+
+        let names = schema.fields.iter().map(|f| f.name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["count", "items", "name", "fixed", "skipped", "extra"]
+        );
+
+        let items = schema.field("items").unwrap();
+        assert_eq!(items.rust_type, "Vec<u16>");
+        let count = match items.wire {
+            WireType::List(Length::Counted(count), WireType::U16) => count,
+            wire => panic!("unexpected wire type: {:?}", wire),
+        };
+
+        // counts are evaluated with the types of the fields they refer to
+        assert_eq!(count.expr, "count");
+        assert_eq!((count.eval)(&|name| Some(name.len() as i128)), Some(5));
+        assert_eq!((count.eval)(&|_| Some(256)), None);
+        assert_eq!((count.eval)(&|_| None), None);
+
+        let name = schema.field("name").unwrap();
+        assert_eq!(
+            name.wire,
+            WireType::String(Length::Prefixed(&WireType::U32))
+        );
+        assert_eq!(schema.field("fixed").unwrap().wire.fixed_len(), Some(3));
+        assert_eq!(schema.field("skipped").unwrap().presence, Presence::Skipped);
+
+        let extra = schema.field("extra").unwrap();
+        assert_eq!(extra.rust_type, "Option<CompressedRLE<Vec<bool>>>");
+        assert_eq!(extra.presence, Presence::Trailing);
+        assert_eq!(
+            extra.wire,
+            WireType::Optional(&WireType::List(
+                Length::Prefixed(&WireType::CompressedInt),
+                &WireType::Bool
+            ))
+        );
+    }
+
+    #[test]
+    fn test_packet_schemas() {
+        for &typ in PacketType::get_all_types() {
+            let schema = typ.schema();
+            assert_eq!(schema.name, typ.get_name());
+        }
+
+        let schema = PacketType::Move.schema();
+        assert_eq!(schema.field("new_pos").unwrap().wire, WorldPosData::WIRE);
+        assert_eq!(WorldPosData::WIRE.fixed_len(), Some(8));
+
+        let records = schema.field("records").unwrap();
+        assert_eq!(records.rust_type, "RLE<Vec<MoveRecord>>");
+        match records.wire {
+            WireType::List(Length::Prefixed(WireType::U16), item) => {
+                assert_eq!(item.fixed_len(), Some(12))
+            }
+            wire => panic!("unexpected wire type: {:?}", wire),
+        }
+
+        let schema = PacketType::Pic.schema();
+        match schema.field("bitmap_data").unwrap().wire {
+            WireType::List(Length::Counted(count), WireType::U8) => {
+                let size =
+                    |w: i128, h: i128| (count.eval)(&|name| Some(if name == "w" { w } else { h }));
+                assert_eq!(size(3, 5), Some(60));
+                assert_eq!(size(-1, 5), None);
+                assert_eq!(size(u32::MAX.into(), u32::MAX.into()), Some(usize::MAX));
+            }
+            wire => panic!("unexpected wire type: {:?}", wire),
+        }
+
+        // the fixed length matches the encoded length of every value
+        let pos = WorldPosData { x: 1.0, y: 2.0 };
+        assert_eq!(WorldPosData::WIRE.fixed_len(), Some(pos.encoded_len()));
+        assert_eq!(StatData::WIRE.fixed_len(), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! A human-readable text format for packets, used to write fixtures and
+//! author packets by hand.
+//!
+//! # Format
+//!
+//! Packets are written as JSON objects, based on their serde representation:
+//!
+//! - the `type` field holds the name of the packet type, e.g. `"Hello"`
+//! - every other field of the packet is written using its name in the packet
+//!   struct, and all fields are required
+//! - byte vectors (such as `Hello.key` or `Pic.bitmap_data`) are written as
+//!   lowercase hexadecimal strings, e.g. `"00ff10"`
+//! - `RLE` wrappers are transparent, so strings and lists are written as-is
+//! - result codes and other integer enums (such as `FailureCode`) are written
+//!   as their integer value
+//! - stats are written as `{ "Integer": ["HP_STAT", 100] }`,
+//!   `{ "String": ["NAME_STAT", "name"] }`, or `{ "Unknown": [id, value] }`
+//! - packets with unknown IDs are written as
+//!   `{ "type": "Unknown", "id": 123, "payload": "00ff" }`
+//!
+//! Multiple packets can be written as a JSON array of packet objects.
+//!
+//! # Examples
+//!
+//! ```
+//! # use rotmg_packets::packets::{Packet, client::Pong};
+//! # use rotmg_packets::text::{from_text, to_text};
+//! let packet = from_text(r#"{ "type": "Pong", "serial": 1, "time": 2 }"#).unwrap();
+//! assert_eq!(packet, Packet::Pong(Pong { serial: 1, time: 2 }));
+//!
+//! assert_eq!(from_text(&to_text(&packet)).unwrap(), packet);
+//! ```
+
+use crate::adapter::RLE;
+use crate::packets::Packet;
+use crate::prelude::*;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::result::Result as StdResult;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An error parsing packets from text
+#[derive(Debug)]
+pub struct TextError(serde_json::Error);
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Invalid packet text: {}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TextError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Write a packet in the text format
+pub fn to_text(packet: &Packet) -> String {
+    serde_json::to_string_pretty(packet).expect("packets can always be printed")
+}
+
+/// Parse a packet from the text format
+pub fn from_text(text: &str) -> StdResult<Packet, TextError> {
+    serde_json::from_str(text).map_err(TextError)
+}
+
+/// Write a list of packets in the text format
+pub fn to_text_list(packets: &[Packet]) -> String {
+    serde_json::to_string_pretty(packets).expect("packets can always be printed")
+}
+
+/// Parse a list of packets from the text format
+pub fn from_text_list(text: &str) -> StdResult<Vec<Packet>, TextError> {
+    serde_json::from_str(text).map_err(TextError)
+}
+
+/// A type holding a vector of bytes, which can be written as hex
+pub trait ByteVec: Sized {
+    /// Get the bytes contained in this value
+    fn bytes(&self) -> &[u8];
+
+    /// Create a value from a vector of bytes
+    fn from_bytes(bytes: Vec<u8>) -> Self;
+}
+
+impl ByteVec for Vec<u8> {
+    fn bytes(&self) -> &[u8] {
+        &self[..]
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        bytes
+    }
+}
+
+impl<S> ByteVec for RLE<Vec<u8>, S> {
+    fn bytes(&self) -> &[u8] {
+        &self[..]
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        RLE::new(bytes)
+    }
+}
+
+/// Serialize and deserialize byte vectors as hex strings in human-readable
+/// formats, for use with `#[serde(with = "crate::text::hex_bytes")]`. Other
+/// formats are unaffected.
+pub mod hex_bytes {
+    use super::*;
+
+    /// Serialize a byte vector
+    pub fn serialize<T, S>(value: &T, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        T: ByteVec,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            hex::encode(value.bytes()).serialize(serializer)
+        } else {
+            value.bytes().serialize(serializer)
+        }
+    }
+
+    /// Deserialize a byte vector
+    pub fn deserialize<'de, T, D>(deserializer: D) -> StdResult<T, D::Error>
+    where
+        T: ByteVec,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            hex::decode(&text)
+                .map(T::from_bytes)
+                .map_err(|e| D::Error::custom(format!("invalid hex {:?}: {}", text, e)))
+        } else {
+            Vec::deserialize(deserializer).map(T::from_bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::server::Pic;
+
+    #[test]
+    fn test_text_round_trip() {
+        let packets = vec![
+            Packet::Pic(Pic {
+                w: 1,
+                h: 1,
+                bitmap_data: vec![0, 0x7f, 0x80, 0xff],
+            }),
+            Packet::Unknown {
+                id: 200,
+                payload: vec![1, 2, 3],
+            },
+        ];
+
+        let text = to_text(&packets[0]);
+        assert!(text.contains(r#""bitmap_data": "007f80ff""#));
+        assert_eq!(from_text(&text).unwrap(), packets[0]);
+
+        let text = to_text_list(&packets);
+        assert!(text.contains(r#""payload": "010203""#));
+        assert_eq!(from_text_list(&text).unwrap(), packets);
+
+        let hello = from_text(
+            r#"{
+                "type": "Hello",
+                "build_version": "X31.2.3",
+                "game_id": 4294967294,
+                "guid": "",
+                "rand1": 0,
+                "password": "",
+                "rand2": 0,
+                "secret": "",
+                "key_time": 0,
+                "key": "abcd",
+                "map_json": "",
+                "entry_tag": "",
+                "game_net": "rotmg",
+                "game_net_user_id": "",
+                "play_platform": "rotmg",
+                "platform_token": "",
+                "user_token": ""
+            }"#,
+        )
+        .unwrap();
+
+        match hello {
+            Packet::Hello(hello) => assert_eq!(hello.key, RLE::<Vec<u8>>::new(vec![0xab, 0xcd])),
+            p => panic!("unexpected packet: {:?}", p),
+        }
+
+        assert!(from_text(r#"{ "type": "Pic", "w": 0, "h": 0, "bitmap_data": "xyz" }"#).is_err());
+    }
+}
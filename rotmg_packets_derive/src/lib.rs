@@ -0,0 +1,526 @@
+//! Procedural macros for the rotmg_packets crate
+//!
+//! This crate provides `#[derive(Adapter)]` and `#[derive(Schema)]`, which
+//! generate implementations of `rotmg_packets::adapter::Adapter` and
+//! `rotmg_packets::schema::Schema` for structs. It shouldn't be used directly -
+//! the derive macros are re-exported by `rotmg_packets::adapter` and
+//! `rotmg_packets::schema`.
+
+#![deny(missing_docs)]
+#![deny(bare_trait_objects)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Expr, Fields, GenericParam, Ident,
+    Index, Member, Result, Token, Type,
+};
+
+/// Derive `Adapter` for a struct, encoding each field in declaration order.
+///
+/// The encoding of individual fields can be customized using the `adapter`
+/// attribute:
+///
+/// - `#[adapter(len = T)]` - encode a `Vec` or `String` prefixed with its
+///   length, using the integer type `T` for the prefix
+/// - `#[adapter(count = expr)]` - encode a `Vec` or `String` without a length
+///   prefix, decoding the number of items given by `expr`, which may refer to
+///   any previously decoded fields by name
+/// - `#[adapter(trailing)]` - only decode the field when bytes remain in the
+///   buffer, using `Default::default()` otherwise. Every field after a
+///   trailing field must also be trailing (or skipped).
+/// - `#[adapter(skip)]` - don't encode the field at all, decoding it as
+///   `Default::default()`, or as the value of `default = expr` if given
+#[proc_macro_derive(Adapter, attributes(adapter))]
+pub fn derive_adapter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_adapter(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derive `Schema` for a struct, describing the binary format of each field.
+///
+/// The `adapter` attributes accepted by `#[derive(Adapter)]` are also used by
+/// this macro, so that the schema matches the derived adapter. Every field
+/// type must implement `Schema`, or `SequenceSchema` for fields with `len` or
+/// `count` attributes.
+#[proc_macro_derive(Schema, attributes(adapter))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_schema(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// A single argument passed to an `adapter` attribute
+enum Arg {
+    Len(Type),
+    Count(Expr),
+    Trailing,
+    Skip,
+    Default(Expr),
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name: Ident = input.parse()?;
+
+        match name.to_string().as_str() {
+            "len" => {
+                input.parse::<Token![=]>()?;
+                Ok(Arg::Len(input.parse()?))
+            }
+            "count" => {
+                input.parse::<Token![=]>()?;
+                Ok(Arg::Count(input.parse()?))
+            }
+            "default" => {
+                input.parse::<Token![=]>()?;
+                Ok(Arg::Default(input.parse()?))
+            }
+            "trailing" => Ok(Arg::Trailing),
+            "skip" => Ok(Arg::Skip),
+            _ => Err(Error::new(name.span(), "unknown adapter attribute")),
+        }
+    }
+}
+
+/// The way the value of a field is represented in binary form
+enum Encoding {
+    /// Use the `Adapter` implementation for the field type
+    Plain,
+    /// Encode a sequence prefixed by its length, of the given type
+    Prefixed(Type),
+    /// Encode a sequence without a prefix, with the length given by an
+    /// expression
+    Counted(Expr),
+    /// Don't encode the field, decoding it using the given expression
+    Skipped(Expr),
+}
+
+/// A field of the struct the adapter is being derived for
+struct FieldSpec {
+    name: String,
+    member: Member,
+    binding: Ident,
+    ty: Type,
+    encoding: Encoding,
+    trailing: bool,
+}
+
+impl FieldSpec {
+    fn new(index: usize, field: &syn::Field) -> Result<Self> {
+        let (member, binding) = match &field.ident {
+            Some(ident) => (Member::Named(ident.clone()), ident.clone()),
+            None => (
+                Member::Unnamed(Index::from(index)),
+                format_ident!("field{}", index),
+            ),
+        };
+
+        let mut len = None;
+        let mut count = None;
+        let mut default = None;
+        let mut trailing = false;
+        let mut skip = false;
+
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("adapter")) {
+            let args = attr.parse_args_with(Punctuated::<Arg, Token![,]>::parse_terminated)?;
+
+            for arg in args {
+                match arg {
+                    Arg::Len(ty) => len = Some(ty),
+                    Arg::Count(expr) => count = Some(expr),
+                    Arg::Default(expr) => default = Some(expr),
+                    Arg::Trailing => trailing = true,
+                    Arg::Skip => skip = true,
+                }
+            }
+        }
+
+        let encoding = match (len, count, skip) {
+            (None, None, false) => Encoding::Plain,
+            (Some(ty), None, false) => Encoding::Prefixed(ty),
+            (None, Some(expr), false) => Encoding::Counted(expr),
+            (None, None, true) => Encoding::Skipped(
+                default
+                    .take()
+                    .unwrap_or_else(|| parse_quote!(::core::default::Default::default())),
+            ),
+            _ => {
+                return Err(Error::new(
+                    field.span(),
+                    "only one of `len`, `count` and `skip` may be used on a field",
+                ))
+            }
+        };
+
+        if default.is_some() {
+            return Err(Error::new(
+                field.span(),
+                "`default` may only be used on skipped fields",
+            ));
+        }
+
+        if trailing && skip {
+            return Err(Error::new(
+                field.span(),
+                "skipped fields may not be trailing",
+            ));
+        }
+
+        Ok(Self {
+            name: match &member {
+                Member::Named(ident) => ident.to_string(),
+                Member::Unnamed(index) => index.index.to_string(),
+            },
+            member,
+            binding,
+            ty: field.ty.clone(),
+            encoding,
+            trailing,
+        })
+    }
+
+    /// Generate an expression decoding this field from `__buffer`, passing on
+    /// `__options`. Errors have the name of this field added to their path.
+    fn decode(&self) -> TokenStream2 {
+        let ty = &self.ty;
+        let name = &self.name;
+
+        let result = match &self.encoding {
+            Encoding::Plain => quote! {
+                <#ty as ::rotmg_packets::adapter::Adapter>::get_be_with(__buffer, __options)
+            },
+            Encoding::Prefixed(prefix) => quote! {
+                <#ty as ::rotmg_packets::adapter::Sequence>::get_prefixed_with::<#prefix, _>(
+                    __buffer,
+                    __options,
+                )
+            },
+            Encoding::Counted(count) => quote! {
+                <#ty as ::rotmg_packets::adapter::Sequence>::get_items_with(
+                    __buffer,
+                    (#count) as usize,
+                    __options,
+                )
+            },
+            Encoding::Skipped(default) => return quote! { #default },
+        };
+
+        let value = quote! {
+            #result.map_err(|e| {
+                e.in_field(#name, ::rotmg_packets::adapter::Buf::remaining(__buffer))
+            })?
+        };
+
+        if self.trailing {
+            quote! {
+                if ::rotmg_packets::adapter::Buf::has_remaining(__buffer) {
+                    #value
+                } else {
+                    ::core::default::Default::default()
+                }
+            }
+        } else {
+            value
+        }
+    }
+
+    /// Generate a statement encoding this field into `__buffer`. Counted
+    /// sequences are checked against their count, which may refer to any of
+    /// the `previous` fields.
+    fn encode(&self, previous: &[FieldSpec]) -> TokenStream2 {
+        let member = &self.member;
+
+        match &self.encoding {
+            Encoding::Plain => quote! {
+                ::rotmg_packets::adapter::Adapter::put_be(&self.#member, __buffer)?;
+            },
+            Encoding::Prefixed(prefix) => quote! {
+                ::rotmg_packets::adapter::Sequence::put_prefixed::<#prefix, _>(&self.#member, __buffer)?;
+            },
+            Encoding::Counted(count) => {
+                // bind the fields used by the count, as they are when decoding
+                let idents = idents(quote!(#count));
+                let used = previous.iter().filter(|s| idents.contains(&s.binding));
+                let bindings = used.clone().map(|s| &s.binding);
+                let members = used.map(|s| &s.member);
+
+                quote! {
+                    {
+                        #( let #bindings = ::core::clone::Clone::clone(&self.#members); )*
+                        ::rotmg_packets::adapter::Sequence::put_counted(
+                            &self.#member,
+                            (#count) as usize,
+                            __buffer,
+                        )?;
+                    }
+                }
+            }
+            Encoding::Skipped(_) => quote! {},
+        }
+    }
+
+    /// Generate an expression for the number of bytes this field will be
+    /// encoded as
+    fn encoded_len(&self) -> TokenStream2 {
+        let member = &self.member;
+
+        match &self.encoding {
+            Encoding::Plain => quote! {
+                ::rotmg_packets::adapter::Adapter::encoded_len(&self.#member)
+            },
+            Encoding::Prefixed(prefix) => quote! {
+                ::rotmg_packets::adapter::Sequence::prefixed_len::<#prefix>(&self.#member)
+            },
+            Encoding::Counted(_) => quote! {
+                ::rotmg_packets::adapter::Sequence::items_len(&self.#member)
+            },
+            Encoding::Skipped(_) => quote! { 0 },
+        }
+    }
+
+    /// Generate an expression describing this field as a `FieldSchema`. The
+    /// `count` of a counted sequence may refer to any of the `previous`
+    /// fields.
+    fn schema(&self, previous: &[FieldSpec]) -> TokenStream2 {
+        let name = &self.name;
+        let ty = &self.ty;
+        let rust_type = type_name(ty);
+
+        let wire = match &self.encoding {
+            Encoding::Plain => quote! {
+                <#ty as ::rotmg_packets::schema::Schema>::WIRE
+            },
+            Encoding::Prefixed(prefix) => quote! {
+                ::rotmg_packets::schema::WireType::sequence::<#ty>(
+                    ::rotmg_packets::schema::Length::Prefixed(
+                        &<#prefix as ::rotmg_packets::schema::Schema>::WIRE,
+                    ),
+                )
+            },
+            Encoding::Counted(count) => {
+                let expr = quote!(#count).to_string();
+
+                // the fields used by the count are looked up by name, and
+                // converted to their own types so it's evaluated as when
+                // decoding
+                let idents = idents(quote!(#count));
+                let used = previous.iter().filter(|s| idents.contains(&s.binding));
+                let bindings = used.clone().map(|s| &s.binding);
+                let types = used.clone().map(|s| &s.ty);
+                let names = used.map(|s| &s.name);
+
+                quote! {
+                    ::rotmg_packets::schema::WireType::sequence::<#ty>(
+                        ::rotmg_packets::schema::Length::Counted({
+                            fn __count(
+                                __field: ::rotmg_packets::schema::FieldLookup,
+                            ) -> ::core::option::Option<usize> {
+                                #(
+                                    let #bindings: #types =
+                                        ::core::convert::TryFrom::try_from(__field(#names)?).ok()?;
+                                )*
+                                ::core::convert::TryFrom::try_from(#count).ok()
+                            }
+
+                            const __COUNT: ::rotmg_packets::schema::CountSchema =
+                                ::rotmg_packets::schema::CountSchema {
+                                    expr: #expr,
+                                    eval: __count,
+                                };
+
+                            &__COUNT
+                        }),
+                    )
+                }
+            }
+            // nothing at all is encoded
+            Encoding::Skipped(_) => quote! {
+                ::rotmg_packets::schema::WireType::Tuple(&[])
+            },
+        };
+
+        let presence = match (&self.encoding, self.trailing) {
+            (Encoding::Skipped(_), _) => quote!(Skipped),
+            (_, true) => quote!(Trailing),
+            (_, false) => quote!(Always),
+        };
+
+        quote! {
+            ::rotmg_packets::schema::FieldSchema {
+                name: #name,
+                rust_type: #rust_type,
+                wire: #wire,
+                presence: ::rotmg_packets::schema::Presence::#presence,
+            }
+        }
+    }
+}
+
+/// Parse the fields of the struct a macro is being derived for
+fn parse_fields(input: &DeriveInput) -> Result<Vec<FieldSpec>> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new(input.ident.span(), "only structs are supported")),
+    };
+
+    let specs = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| FieldSpec::new(i, f))
+        .collect::<Result<Vec<_>>>()?;
+
+    // trailing fields are only meaningful at the end of the struct
+    if let Some(first) = specs.iter().position(|s| s.trailing) {
+        if let Some(bad) = specs[first..]
+            .iter()
+            .find(|s| !s.trailing && !matches!(s.encoding, Encoding::Skipped(_)))
+        {
+            return Err(Error::new(
+                bad.binding.span(),
+                "fields following a trailing field must also be trailing",
+            ));
+        }
+    }
+
+    Ok(specs)
+}
+
+/// Add a bound on the given trait to every type parameter of the struct
+fn add_bounds(input: &mut DeriveInput, bound: TokenStream2) {
+    for param in input.generics.params.iter_mut() {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(#bound));
+        }
+    }
+}
+
+/// Collect every identifier in the given tokens
+fn idents(tokens: TokenStream2) -> Vec<Ident> {
+    let mut idents = vec![];
+
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => idents.push(ident),
+            TokenTree::Group(group) => idents.extend(self::idents(group.stream())),
+            _ => {}
+        }
+    }
+
+    idents
+}
+
+/// Format a type as it would be written in source code
+fn type_name(ty: &Type) -> String {
+    let tokens = quote!(#ty).to_string();
+    let chars = tokens.chars().collect::<Vec<_>>();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut name = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            // only keep spaces separating words, e.g. in `dyn Trait`
+            ' ' => {
+                if i > 0 && is_word(chars[i - 1]) && chars.get(i + 1).is_some_and(|&c| is_word(c)) {
+                    name.push(' ');
+                }
+            }
+            ',' | ';' => name.push_str(&format!("{} ", c)),
+            c => name.push(c),
+        }
+    }
+
+    name
+}
+
+fn expand_adapter(mut input: DeriveInput) -> Result<TokenStream2> {
+    let specs = parse_fields(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => unreachable!("only structs are supported"),
+    };
+
+    let bindings = specs.iter().map(|s| &s.binding).collect::<Vec<_>>();
+    let decoders = specs.iter().map(FieldSpec::decode);
+    let encoders = specs.iter().enumerate().map(|(i, s)| s.encode(&specs[..i]));
+    let lengths = specs.iter().map(FieldSpec::encoded_len);
+
+    let construct = match fields {
+        Fields::Named(_) => quote! { Self { #( #bindings ),* } },
+        Fields::Unnamed(_) => quote! { Self ( #( #bindings ),* ) },
+        Fields::Unit => quote! { Self },
+    };
+
+    // require all type parameters to implement Adapter
+    add_bounds(&mut input, quote!(::rotmg_packets::adapter::Adapter));
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::rotmg_packets::adapter::Adapter for #name #ty_generics #where_clause {
+            fn get_be<__B: ::rotmg_packets::adapter::Buf + ?Sized>(
+                __buffer: &mut __B,
+            ) -> ::rotmg_packets::adapter::Result<Self> {
+                Self::get_be_with(__buffer, &::core::default::Default::default())
+            }
+
+            #[allow(unused_variables)]
+            fn get_be_with<__B: ::rotmg_packets::adapter::Buf + ?Sized>(
+                __buffer: &mut __B,
+                __options: &::rotmg_packets::packets::DecodeOptions,
+            ) -> ::rotmg_packets::adapter::Result<Self> {
+                #( let #bindings = #decoders; )*
+
+                Ok(#construct)
+            }
+
+            #[allow(unused_variables)]
+            fn put_be<__B: ::rotmg_packets::adapter::BufMut + ?Sized>(
+                &self,
+                __buffer: &mut __B,
+            ) -> ::rotmg_packets::adapter::Result<()> {
+                #( #encoders )*
+
+                Ok(())
+            }
+
+            fn encoded_len(&self) -> usize {
+                0 #( + #lengths )*
+            }
+        }
+    })
+}
+
+fn expand_schema(mut input: DeriveInput) -> Result<TokenStream2> {
+    let specs = parse_fields(&input)?;
+    let schemas = specs.iter().enumerate().map(|(i, s)| s.schema(&specs[..i]));
+
+    // require all type parameters to implement Schema
+    add_bounds(&mut input, quote!(::rotmg_packets::schema::Schema));
+
+    let name = &input.ident;
+    let name_str = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::rotmg_packets::schema::Schema for #name #ty_generics #where_clause {
+            const WIRE: ::rotmg_packets::schema::WireType =
+                ::rotmg_packets::schema::WireType::Struct(&::rotmg_packets::schema::StructSchema {
+                    name: #name_str,
+                    fields: &[ #( #schemas ),* ],
+                });
+        }
+    })
+}